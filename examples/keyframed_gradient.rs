@@ -30,10 +30,12 @@ fn main() -> Result<(), MainError> {
         .with_length(Duration::from_secs(10))
         .with_resolution(RESOLUTION_720P)
         .with_fps(30)
+        .with_progress(Box::new(TerminalProgress::new()))
         .add_child(main_renderable)
         .build()
         .change_context(MainError)
         .attach_printable_lazy(|| "Failed to create scene")?
         .render()
-        .change_context(MainError)
+        .change_context(MainError)?;
+    Ok(())
 }
@@ -0,0 +1,60 @@
+use crate::prelude::*;
+
+/// How a renderable's pixels are combined with whatever has already been drawn beneath it.
+/// The separable modes follow the W3C compositing spec (the same definitions After Effects uses), and are composited with premultiplied Porter-Duff "over".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    /// Also known as linear dodge, clamped at white
+    Add,
+    Darken,
+    Lighten,
+    Difference,
+}
+
+impl BlendMode {
+    pub fn blend_channel(&self, backdrop: f32, source: f32) -> f32 {
+        //! Blends a single straight (non-premultiplied) colour channel, with both values in 0.0..=1.0
+        match self {
+            BlendMode::Normal => source,
+            BlendMode::Multiply => backdrop * source,
+            BlendMode::Screen => backdrop + source - backdrop * source,
+            BlendMode::Overlay => {
+                if backdrop <= 0.5 {
+                    2.0 * backdrop * source
+                } else {
+                    1.0 - 2.0 * (1.0 - backdrop) * (1.0 - source)
+                }
+            }
+            BlendMode::Add => (backdrop + source).min(1.0),
+            BlendMode::Darken => backdrop.min(source),
+            BlendMode::Lighten => backdrop.max(source),
+            BlendMode::Difference => (backdrop - source).abs(),
+        }
+    }
+    pub fn composite(&self, backdrop: Rgba<u8>, source: Rgba<u8>) -> Rgba<u8> {
+        //! Composites source over backdrop, both being straight alpha colours.
+        let alpha_s = source.0[3] as f32 / 255.0;
+        let alpha_b = backdrop.0[3] as f32 / 255.0;
+        let alpha_o = alpha_s + alpha_b * (1.0 - alpha_s);
+        if alpha_o <= 0.0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let mut out = [0; 4];
+        for (i, channel) in out.iter_mut().take(3).enumerate() {
+            let c_s = source.0[i] as f32 / 255.0;
+            let c_b = backdrop.0[i] as f32 / 255.0;
+            // Where the backdrop is transparent, the source shows through unblended
+            let blended = (1.0 - alpha_b) * c_s + alpha_b * self.blend_channel(c_b, c_s);
+            // Porter-Duff over in premultiplied space, then back to straight alpha
+            let premultiplied = alpha_s * blended + alpha_b * c_b * (1.0 - alpha_s);
+            *channel = ((premultiplied / alpha_o).clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+        out[3] = (alpha_o * 255.0).round() as u8;
+        Rgba(out)
+    }
+}
@@ -1,8 +1,10 @@
 use core::slice::SlicePattern;
 
+use crate::prelude::*;
 use image::{Rgb, RgbImage, RgbaImage};
 pub use openh264::encoder::RateControlMode;
 use openh264::formats::YUVBuffer;
+use std::io::Write;
 
 pub(crate) fn rgba_to_rgb(image: RgbaImage) -> RgbImage {
     let mut ret = RgbImage::new(image.width(), image.height());
@@ -21,3 +23,421 @@ pub(crate) fn rgba_to_yuv(image: RgbaImage) -> YUVBuffer {
         image.into_raw().as_slice(),
     )
 }
+
+#[derive(ErrorStack, Debug)]
+#[error_message("Failed to mux H.264 stream into an mp4 file")]
+pub struct Mp4MuxingError;
+
+struct Mp4Sample {
+    size: u32,
+    is_sync: bool,
+}
+
+/// Wraps the Annex-B H.264 stream produced by openh264 into an MP4 (ISO BMFF) file, no ffmpeg needed.
+///
+/// Every pushed frame becomes one sample lasting `sample_delta / timescale` seconds, so 30fps is `(30, 1)` and 29.97fps is `(30000, 1001)`.
+pub struct Mp4Muxer {
+    width: u32,
+    height: u32,
+    timescale: u32,
+    sample_delta: u32,
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+    samples: Vec<Mp4Sample>,
+    mdat: Vec<u8>,
+}
+
+impl Mp4Muxer {
+    pub fn new(width: u32, height: u32, timescale: u32, sample_delta: u32) -> Self {
+        Self {
+            width,
+            height,
+            timescale,
+            sample_delta,
+            sps: None,
+            pps: None,
+            samples: vec![],
+            mdat: vec![],
+        }
+    }
+    pub fn push_frame(&mut self, annex_b: &[u8]) {
+        //! Adds one encoded frame, as returned by `Encoder::encode`, as the next sample.
+        let mut size = 0;
+        let mut is_sync = false;
+        for nal in split_nal_units(annex_b) {
+            match nal[0] & 0x1f {
+                7 => {
+                    self.sps.get_or_insert_with(|| nal.to_vec());
+                    continue;
+                }
+                8 => {
+                    self.pps.get_or_insert_with(|| nal.to_vec());
+                    continue;
+                }
+                9 => continue, // Access unit delimiters have no place in mp4 samples
+                5 => is_sync = true,
+                _ => {}
+            }
+            self.mdat
+                .extend_from_slice(&(nal.len() as u32).to_be_bytes());
+            self.mdat.extend_from_slice(nal);
+            size += 4 + nal.len() as u32;
+        }
+        self.samples.push(Mp4Sample { size, is_sync });
+    }
+    pub fn finish<W: Write>(self, writer: &mut W) -> Result<(), Mp4MuxingError> {
+        //! Writes the finished file, with the moov box in front of the media data so it can be streamed.
+        let (sps, pps) = match (&self.sps, &self.pps) {
+            (Some(sps), Some(pps)) => (sps, pps),
+            _ => {
+                return Err(Report::new(Mp4MuxingError))
+                    .attach_printable("The stream contained no SPS/PPS, was anything encoded?")
+            }
+        };
+
+        let ftyp = mp4_box(
+            b"ftyp",
+            &[
+                b"isom".as_slice(),
+                &0x200u32.to_be_bytes(),
+                b"isom",
+                b"iso2",
+                b"avc1",
+                b"mp41",
+            ]
+            .concat(),
+        );
+        let large = self.mdat.len() as u64 + 16 > u32::MAX as u64;
+        let mdat_header_len = if large { 16 } else { 8 };
+
+        // The moov box has the same size whatever chunk offset it holds, so build it once to measure it
+        let moov_len = self.moov(sps, pps, 0, large).len() as u64;
+        let moov = self.moov(
+            sps,
+            pps,
+            ftyp.len() as u64 + moov_len + mdat_header_len,
+            large,
+        );
+
+        let mut mdat_header = vec![];
+        if large {
+            mdat_header.extend_from_slice(&1u32.to_be_bytes());
+            mdat_header.extend_from_slice(b"mdat");
+            mdat_header.extend_from_slice(&(self.mdat.len() as u64 + 16).to_be_bytes());
+        } else {
+            mdat_header.extend_from_slice(&(self.mdat.len() as u32 + 8).to_be_bytes());
+            mdat_header.extend_from_slice(b"mdat");
+        }
+
+        [ftyp.as_slice(), &moov, &mdat_header, &self.mdat]
+            .iter()
+            .try_for_each(|bytes| writer.write_all(bytes))
+            .into_report()
+            .change_context(Mp4MuxingError)
+            .attach_printable_lazy(|| "Failed to write mp4 file")
+    }
+    fn moov(&self, sps: &[u8], pps: &[u8], chunk_offset: u64, large: bool) -> Vec<u8> {
+        let duration = self.samples.len() as u32 * self.sample_delta;
+        let matrix = [0x10000u32, 0, 0, 0, 0x10000, 0, 0, 0, 0x40000000]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect::<Vec<u8>>();
+
+        let mut mvhd = vec![0; 8]; // creation and modification time
+        mvhd.extend_from_slice(&self.timescale.to_be_bytes());
+        mvhd.extend_from_slice(&duration.to_be_bytes());
+        mvhd.extend_from_slice(&0x10000u32.to_be_bytes()); // rate
+        mvhd.extend_from_slice(&0x100u16.to_be_bytes()); // volume
+        mvhd.extend_from_slice(&[0; 10]);
+        mvhd.extend_from_slice(&matrix);
+        mvhd.extend_from_slice(&[0; 24]);
+        mvhd.extend_from_slice(&2u32.to_be_bytes()); // next track id
+
+        let mut tkhd = vec![0; 8];
+        tkhd.extend_from_slice(&1u32.to_be_bytes()); // track id
+        tkhd.extend_from_slice(&[0; 4]);
+        tkhd.extend_from_slice(&duration.to_be_bytes());
+        tkhd.extend_from_slice(&[0; 16]); // reserved, layer, alternate group, volume, reserved
+        tkhd.extend_from_slice(&matrix);
+        tkhd.extend_from_slice(&(self.width << 16).to_be_bytes());
+        tkhd.extend_from_slice(&(self.height << 16).to_be_bytes());
+
+        let mut mdhd = vec![0; 8];
+        mdhd.extend_from_slice(&self.timescale.to_be_bytes());
+        mdhd.extend_from_slice(&duration.to_be_bytes());
+        mdhd.extend_from_slice(&0x55c4u16.to_be_bytes()); // language: und
+        mdhd.extend_from_slice(&[0; 2]);
+
+        let mut hdlr = vec![0; 4];
+        hdlr.extend_from_slice(b"vide");
+        hdlr.extend_from_slice(&[0; 12]);
+        hdlr.extend_from_slice(b"VideoHandler\0");
+
+        let mut avcc = vec![1, sps[1], sps[2], sps[3], 0xff, 0xe1];
+        avcc.extend_from_slice(&(sps.len() as u16).to_be_bytes());
+        avcc.extend_from_slice(sps);
+        avcc.push(1);
+        avcc.extend_from_slice(&(pps.len() as u16).to_be_bytes());
+        avcc.extend_from_slice(pps);
+
+        let mut avc1 = vec![0; 6];
+        avc1.extend_from_slice(&1u16.to_be_bytes()); // data reference index
+        avc1.extend_from_slice(&[0; 16]);
+        avc1.extend_from_slice(&(self.width as u16).to_be_bytes());
+        avc1.extend_from_slice(&(self.height as u16).to_be_bytes());
+        avc1.extend_from_slice(&0x480000u32.to_be_bytes()); // 72 dpi
+        avc1.extend_from_slice(&0x480000u32.to_be_bytes());
+        avc1.extend_from_slice(&[0; 4]);
+        avc1.extend_from_slice(&1u16.to_be_bytes()); // frame count
+        avc1.extend_from_slice(&[0; 32]); // compressor name
+        avc1.extend_from_slice(&0x18u16.to_be_bytes()); // depth
+        avc1.extend_from_slice(&(-1i16).to_be_bytes());
+        avc1.extend_from_slice(&mp4_box(b"avcC", &avcc));
+
+        let mut stsd = 1u32.to_be_bytes().to_vec();
+        stsd.extend_from_slice(&mp4_box(b"avc1", &avc1));
+
+        let mut stts = 1u32.to_be_bytes().to_vec();
+        stts.extend_from_slice(&(self.samples.len() as u32).to_be_bytes());
+        stts.extend_from_slice(&self.sample_delta.to_be_bytes());
+
+        let sync_samples = self
+            .samples
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.is_sync)
+            .map(|(i, _)| i as u32 + 1)
+            .collect::<Vec<u32>>();
+        let mut stss = (sync_samples.len() as u32).to_be_bytes().to_vec();
+        sync_samples
+            .iter()
+            .for_each(|i| stss.extend_from_slice(&i.to_be_bytes()));
+
+        // Every sample lives in a single chunk
+        let mut stsc = 1u32.to_be_bytes().to_vec();
+        stsc.extend_from_slice(&1u32.to_be_bytes());
+        stsc.extend_from_slice(&(self.samples.len() as u32).to_be_bytes());
+        stsc.extend_from_slice(&1u32.to_be_bytes());
+
+        let mut stsz = 0u32.to_be_bytes().to_vec();
+        stsz.extend_from_slice(&(self.samples.len() as u32).to_be_bytes());
+        self.samples
+            .iter()
+            .for_each(|s| stsz.extend_from_slice(&s.size.to_be_bytes()));
+
+        let mut chunk_offsets = 1u32.to_be_bytes().to_vec();
+        let chunk_offsets = if large {
+            chunk_offsets.extend_from_slice(&chunk_offset.to_be_bytes());
+            mp4_full_box(b"co64", 0, &chunk_offsets)
+        } else {
+            chunk_offsets.extend_from_slice(&(chunk_offset as u32).to_be_bytes());
+            mp4_full_box(b"stco", 0, &chunk_offsets)
+        };
+
+        let stbl = [
+            mp4_full_box(b"stsd", 0, &stsd),
+            mp4_full_box(b"stts", 0, &stts),
+            mp4_full_box(b"stss", 0, &stss),
+            mp4_full_box(b"stsc", 0, &stsc),
+            mp4_full_box(b"stsz", 0, &stsz),
+            chunk_offsets,
+        ]
+        .concat();
+
+        let dref = mp4_full_box(
+            b"dref",
+            0,
+            &[
+                1u32.to_be_bytes().as_slice(),
+                &mp4_full_box(b"url ", 1, &[]),
+            ]
+            .concat(),
+        );
+        let minf = [
+            mp4_full_box(b"vmhd", 1, &[0; 8]),
+            mp4_box(b"dinf", &dref),
+            mp4_box(b"stbl", &stbl),
+        ]
+        .concat();
+        let mdia = [
+            mp4_full_box(b"mdhd", 0, &mdhd),
+            mp4_full_box(b"hdlr", 0, &hdlr),
+            mp4_box(b"minf", &minf),
+        ]
+        .concat();
+        let trak = [mp4_full_box(b"tkhd", 3, &tkhd), mp4_box(b"mdia", &mdia)].concat();
+
+        mp4_box(
+            b"moov",
+            &[mp4_full_box(b"mvhd", 0, &mvhd), mp4_box(b"trak", &trak)].concat(),
+        )
+    }
+}
+
+fn mp4_box(name: &[u8; 4], content: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(content.len() + 8);
+    ret.extend_from_slice(&(content.len() as u32 + 8).to_be_bytes());
+    ret.extend_from_slice(name);
+    ret.extend_from_slice(content);
+    ret
+}
+
+fn mp4_full_box(name: &[u8; 4], flags: u32, content: &[u8]) -> Vec<u8> {
+    //! Version is always 0, flags are only 24 bits wide
+    mp4_box(name, &[flags.to_be_bytes().as_slice(), content].concat())
+}
+
+pub(crate) fn split_nal_units(annex_b: &[u8]) -> impl Iterator<Item = &[u8]> {
+    //! Splits an Annex-B byte stream on its `00 00 01` / `00 00 00 01` start codes.
+    let mut starts = vec![];
+    let mut i = 0;
+    while i + 3 <= annex_b.len() {
+        if annex_b[i..i + 3] == [0, 0, 1] {
+            starts.push(i + 3);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    let ends = starts
+        .iter()
+        .skip(1)
+        .map(|&s| {
+            let end = s - 3;
+            if end > 0 && annex_b[end - 1] == 0 {
+                end - 1
+            } else {
+                end
+            }
+        })
+        .chain(std::iter::once(annex_b.len()))
+        .collect::<Vec<_>>();
+    starts
+        .into_iter()
+        .zip(ends)
+        .map(move |(s, e)| &annex_b[s..e])
+        .filter(|nal| !nal.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPS: [u8; 5] = [0x67, 0x42, 0x00, 0x1e, 0xab];
+    const PPS: [u8; 4] = [0x68, 0xce, 0x38, 0x80];
+    const IDR: [u8; 4] = [0x65, 0x88, 0x84, 0x21];
+    const P_SLICE: [u8; 2] = [0x41, 0x9a];
+
+    fn boxes(data: &[u8]) -> Vec<([u8; 4], usize, &[u8])> {
+        //! Splits a run of boxes into (name, offset, content), checking their sizes add up exactly
+        let mut ret = vec![];
+        let mut offset = 0;
+        while offset < data.len() {
+            let size = u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+            assert!(
+                size >= 8 && offset + size <= data.len(),
+                "bad box size {}",
+                size
+            );
+            let name = data[offset + 4..offset + 8].try_into().unwrap();
+            ret.push((name, offset, &data[offset + 8..offset + size]));
+            offset += size;
+        }
+        assert_eq!(offset, data.len());
+        ret
+    }
+
+    fn child<'a>(data: &'a [u8], name: &[u8; 4]) -> &'a [u8] {
+        boxes(data)
+            .into_iter()
+            .find(|(n, _, _)| n == name)
+            .unwrap_or_else(|| panic!("no {} box", String::from_utf8_lossy(name)))
+            .2
+    }
+
+    #[test]
+    fn split_nal_units_handles_both_start_codes() {
+        let stream = [
+            &[0, 0, 0, 1][..],
+            &SPS,
+            &[0, 0, 1],
+            &PPS,
+            &[0, 0, 0, 1],
+            &IDR,
+            &[0, 0, 1],
+            &P_SLICE,
+        ]
+        .concat();
+        let nals = split_nal_units(&stream).collect::<Vec<_>>();
+        assert_eq!(nals, vec![&SPS[..], &PPS, &IDR, &P_SLICE]);
+    }
+
+    #[test]
+    fn muxed_file_points_at_its_samples() {
+        let mut muxer = Mp4Muxer::new(16, 16, 30000, 1001);
+        muxer.push_frame(
+            &[
+                &[0, 0, 0, 1][..],
+                &SPS,
+                &[0, 0, 0, 1],
+                &PPS,
+                &[0, 0, 0, 1],
+                &IDR,
+            ]
+            .concat(),
+        );
+        muxer.push_frame(&[&[0, 0, 1][..], &P_SLICE].concat());
+        let mut file = vec![];
+        muxer.finish(&mut file).unwrap();
+
+        let top = boxes(&file);
+        let names = top.iter().map(|(name, _, _)| name).collect::<Vec<_>>();
+        assert_eq!(names, [b"ftyp", b"moov", b"mdat"]);
+        let (_, mdat_offset, mdat) = top[2];
+        assert_eq!(
+            mdat,
+            [&[0, 0, 0, 4][..], &IDR, &[0, 0, 0, 2], &P_SLICE].concat()
+        );
+
+        let stbl = child(
+            child(child(child(top[1].2, b"trak"), b"mdia"), b"minf"),
+            b"stbl",
+        );
+        let stco = child(stbl, b"stco");
+        assert_eq!(&stco[4..8], 1u32.to_be_bytes());
+        assert_eq!(
+            u32::from_be_bytes(stco[8..12].try_into().unwrap()) as usize,
+            mdat_offset + 8
+        );
+        let stsz = child(stbl, b"stsz");
+        assert_eq!(
+            &stsz[8..],
+            [
+                &2u32.to_be_bytes()[..],
+                &8u32.to_be_bytes(),
+                &6u32.to_be_bytes()
+            ]
+            .concat()
+        );
+        let stss = child(stbl, b"stss");
+        assert_eq!(
+            &stss[4..],
+            [&1u32.to_be_bytes()[..], &1u32.to_be_bytes()].concat()
+        );
+        let stts = child(stbl, b"stts");
+        assert_eq!(
+            &stts[4..],
+            [
+                &1u32.to_be_bytes()[..],
+                &2u32.to_be_bytes(),
+                &1001u32.to_be_bytes()
+            ]
+            .concat()
+        );
+
+        //stsd has an entry count before its entries, avc1 has 78 bytes of sample entry fields before its boxes
+        let avc1 = child(&child(stbl, b"stsd")[8..], b"avc1");
+        let avcc = child(&avc1[78..], b"avcC");
+        assert_eq!(&avcc[1..4], &SPS[1..4]);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::output::{Frame, FrameFormat, OutputSink, OutputSinkError, SinkInfo};
+use crate::prelude::*;
+use crate::scene::SceneRenderingError;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+/// Where to find the ffmpeg binary used to encode alpha renders (`Scene::render_alpha`), set with `SceneBuilder::with_ffmpeg`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum FFMPEGLocation {
+    /// Use an installed ffmpeg, downloading one next to the executable if there is none (needs network).
+    #[default]
+    AutoDownload,
+    /// Look `ffmpeg` up on the `PATH`.
+    SystemPath,
+    /// Use the ffmpeg binary at this exact path.
+    Path(PathBuf),
+    /// Never run ffmpeg.
+    Disabled,
+}
+
+impl FFMPEGLocation {
+    pub fn find(&self) -> Result<Option<PathBuf>, SceneRenderingError> {
+        //! Returns the path of a working ffmpeg binary, or `None` if ffmpeg is disabled.
+        let path = match self {
+            FFMPEGLocation::Disabled => return Ok(None),
+            FFMPEGLocation::AutoDownload => {
+                ffmpeg_sidecar::download::auto_download().map_err(|e| {
+                    Report::new(SceneRenderingError::FFMPEGError)
+                        .attach_printable(format!("Failed to download ffmpeg: {}", e))
+                })?;
+                ffmpeg_sidecar::paths::ffmpeg_path()
+            }
+            FFMPEGLocation::SystemPath => PathBuf::from("ffmpeg"),
+            FFMPEGLocation::Path(path) => path.clone(),
+        };
+        let runs = Command::new(&path)
+            .arg("-version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false);
+        if !runs {
+            return Err(Report::new(SceneRenderingError::FFMPEGError)).attach_printable(format!(
+                "No working ffmpeg binary found at {}",
+                path.display()
+            ));
+        }
+        Ok(Some(path))
+    }
+}
+
+/// Codecs that keep the alpha channel, for exporting overlays with [`FFMPEGSink`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaProfile {
+    /// ProRes 4444, in a `.mov`.
+    ProRes4444,
+    /// VP9 with an alpha channel, in a `.webm`.
+    WebmVp9,
+    /// Lossless QuickTime Animation (RLE), in a `.mov`.
+    QuickTimeAnimation,
+}
+
+impl AlphaProfile {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AlphaProfile::ProRes4444 | AlphaProfile::QuickTimeAnimation => "mov",
+            AlphaProfile::WebmVp9 => "webm",
+        }
+    }
+    pub fn codec_args(&self) -> Vec<String> {
+        let args: &[&str] = match self {
+            AlphaProfile::ProRes4444 => &[
+                "-c:v",
+                "prores_ks",
+                "-profile:v",
+                "4444",
+                "-pix_fmt",
+                "yuva444p10le",
+            ],
+            AlphaProfile::WebmVp9 => &[
+                "-c:v",
+                "libvpx-vp9",
+                "-pix_fmt",
+                "yuva420p",
+                "-b:v",
+                "0",
+                "-crf",
+                "20",
+            ],
+            AlphaProfile::QuickTimeAnimation => &["-c:v", "qtrle", "-pix_fmt", "argb"],
+        };
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+}
+
+/// Pipes the raw RGBA frames into an ffmpeg process, which encodes them to `path` with the given codec arguments.
+/// `ffmpeg` is the binary to run, such as one found with `FFMPEGLocation::find`.
+pub struct FFMPEGSink {
+    ffmpeg: PathBuf,
+    path: PathBuf,
+    codec_args: Vec<String>,
+    process: Option<Child>,
+}
+
+impl FFMPEGSink {
+    pub fn new<F: AsRef<Path>, P: AsRef<Path>>(ffmpeg: F, path: P, profile: AlphaProfile) -> Self {
+        Self::with_codec_args(ffmpeg, path, profile.codec_args())
+    }
+    pub fn with_codec_args<F: AsRef<Path>, P: AsRef<Path>>(
+        ffmpeg: F,
+        path: P,
+        codec_args: Vec<String>,
+    ) -> Self {
+        Self {
+            ffmpeg: ffmpeg.as_ref().to_path_buf(),
+            path: path.as_ref().to_path_buf(),
+            codec_args,
+            process: None,
+        }
+    }
+}
+
+impl OutputSink for FFMPEGSink {
+    fn format(&self) -> FrameFormat {
+        FrameFormat::Rgba
+    }
+    fn begin(&mut self, info: SinkInfo) -> Result<(), OutputSinkError> {
+        let process = Command::new(&self.ffmpeg)
+            .args(["-hide_banner", "-loglevel", "error", "-y"])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+            .arg("-s")
+            .arg(format!("{}x{}", info.resolution.x, info.resolution.y))
+            .arg("-r")
+            .arg(info.frame_rate.to_string())
+            .args(["-i", "-"])
+            .args(&self.codec_args)
+            .arg(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .into_report()
+            .change_context(OutputSinkError)
+            .attach_printable_lazy(|| "Failed to start ffmpeg")?;
+        self.process = Some(process);
+        Ok(())
+    }
+    fn write_frame(&mut self, frame_indx: usize, frame: Frame) -> Result<(), OutputSinkError> {
+        let Frame::Rgba(image) = frame else {
+            return Err(Report::new(OutputSinkError))
+                .attach_printable("FFMPEGSink can only pipe RGBA frames");
+        };
+        self.process
+            .as_mut()
+            .and_then(|process| process.stdin.as_mut())
+            .ok_or(Report::new(OutputSinkError))
+            .attach_printable("FFMPEGSink received a frame before begin was called")?
+            .write_all(image.as_raw())
+            .into_report()
+            .change_context(OutputSinkError)
+            .attach_printable_lazy(|| format!("Failed to pipe frame {} to ffmpeg", frame_indx))
+    }
+    fn finish(&mut self) -> Result<(), OutputSinkError> {
+        let mut process = self
+            .process
+            .take()
+            .ok_or(Report::new(OutputSinkError))
+            .attach_printable("FFMPEGSink was finished before begin was called")?;
+        drop(process.stdin.take()); // Closing stdin tells ffmpeg the last frame has arrived
+        let status = process
+            .wait()
+            .into_report()
+            .change_context(OutputSinkError)
+            .attach_printable_lazy(|| "Failed to wait for ffmpeg")?;
+        if !status.success() {
+            return Err(Report::new(OutputSinkError))
+                .attach_printable(format!("ffmpeg failed to encode the video ({})", status));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+/// A frame rate as an exact ratio of frames per second, so broadcast rates like 29.97 (30000/1001) don't drift.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FrameRate {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+pub const FRAME_RATE_23_976: FrameRate = FrameRate::new(24000, 1001);
+pub const FRAME_RATE_24: FrameRate = FrameRate::new(24, 1);
+pub const FRAME_RATE_25: FrameRate = FrameRate::new(25, 1);
+pub const FRAME_RATE_29_97: FrameRate = FrameRate::new(30000, 1001);
+pub const FRAME_RATE_30: FrameRate = FrameRate::new(30, 1);
+pub const FRAME_RATE_50: FrameRate = FrameRate::new(50, 1);
+pub const FRAME_RATE_59_94: FrameRate = FrameRate::new(60000, 1001);
+pub const FRAME_RATE_60: FrameRate = FrameRate::new(60, 1);
+
+impl FrameRate {
+    pub const fn new(numerator: u32, denominator: u32) -> Self {
+        Self {
+            numerator,
+            denominator,
+        }
+    }
+    pub fn as_f64(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+    pub fn frame_time(&self, frame_indx: usize) -> Duration {
+        //! The exact time at which frame `frame_indx` starts.
+        let nanos =
+            frame_indx as u128 * self.denominator as u128 * 1_000_000_000 / self.numerator as u128;
+        Duration::new(
+            (nanos / 1_000_000_000) as u64,
+            (nanos % 1_000_000_000) as u32,
+        )
+    }
+    pub fn frame_at(&self, time: Duration) -> usize {
+        //! The index of the frame being shown at `time`.
+        (time.as_nanos() * self.numerator as u128 / (self.denominator as u128 * 1_000_000_000))
+            as usize
+    }
+    pub fn frames_in(&self, length: Duration) -> usize {
+        //! How many frames it takes to cover `length`, counting a partially covered last frame.
+        let scaled = length.as_nanos() * self.numerator as u128;
+        let frame = self.denominator as u128 * 1_000_000_000;
+        scaled.div_ceil(frame) as usize
+    }
+}
+
+impl From<usize> for FrameRate {
+    fn from(fps: usize) -> Self {
+        Self::new(fps as u32, 1)
+    }
+}
+
+impl Display for FrameRate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        //! Formats as `numerator/denominator`, the way ffmpeg expects rates
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
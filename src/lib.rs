@@ -3,22 +3,42 @@
 //#![warn(missing_docs)]
 
 pub mod prelude {
+    pub use super::blending::BlendMode;
+    pub use super::ffmpeg::{AlphaProfile, FFMPEGLocation};
+    pub use super::frame_rate::*;
+    pub use super::output::{Frame, FrameFormat, OutputSink};
     pub use super::point::Point;
+    pub use super::post_processing::*;
+    #[cfg(feature = "terminal-progress")]
+    pub use super::progress::TerminalProgress;
+    pub use super::progress::{ProgressEvent, RenderProgress};
     pub use super::renderable::{
-        closure_behaviour::ClosureBehaviour, Behaviour, Renderable, RenderableParams,
+        closure_behaviour::ClosureBehaviour,
+        matte::{ChildrenMatte, Matte, MatteMode},
+        scene_behaviour::SceneBehaviour,
+        Behaviour, Renderable, RenderableParams,
     };
     pub use super::resolution_consts::*;
     pub use super::scene::{Img, Scene};
+    pub use super::tools::curves::easing::Easing;
     pub use super::tools::curves::*;
     pub use super::tools::keyframing::*;
+    pub use super::transform::Transform;
     pub(crate) use error_stack::{IntoReport, Report, Result, ResultExt};
     pub(crate) use error_stack_derive::ErrorStack;
     pub use image::Rgba;
 }
 
+pub mod blending;
 pub mod encoding;
+pub mod ffmpeg;
+pub mod frame_rate;
+pub mod output;
 pub mod point;
+pub mod post_processing;
+pub mod progress;
 pub mod renderable;
 pub mod resolution_consts;
 pub mod scene;
 pub mod tools;
+pub mod transform;
@@ -0,0 +1,498 @@
+use crate::encoding::{rgba_to_rgb, Mp4Muxer};
+use crate::prelude::*;
+use color_quant::NeuQuant;
+use image::{DynamicImage, ImageFormat, ImageOutputFormat, RgbaImage};
+use std::borrow::Cow;
+use std::fs::{DirBuilder, File};
+use std::io::{BufWriter, Cursor, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(ErrorStack, Debug)]
+#[error_message("An output sink failed to handle a frame")]
+pub struct OutputSinkError;
+
+fn create_file(path: &Path) -> Result<BufWriter<File>, OutputSinkError> {
+    let file = File::create(path)
+        .into_report()
+        .change_context(OutputSinkError)
+        .attach_printable_lazy(|| format!("Failed to create {}", path.display()))?;
+    Ok(BufWriter::new(file))
+}
+
+/// The kind of frames an [`OutputSink`] wants to receive from [`Scene::render_to`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameFormat {
+    /// Annex-B H.264 bytes straight out of openh264, one encoded frame at a time.
+    H264,
+    /// Uncompressed RGBA frames straight out of the compositor, alpha included.
+    Rgba,
+}
+
+pub enum Frame<'a> {
+    H264(&'a [u8]),
+    Rgba(&'a RgbaImage),
+}
+
+/// Information about the scene being rendered, handed to [`OutputSink::begin`].
+#[derive(Debug, Clone, Copy)]
+pub struct SinkInfo {
+    pub resolution: Point<usize>,
+    pub frame_rate: FrameRate,
+    pub frame_count: usize,
+}
+
+/// Receives the frames of a scene, in order, as they come out of the renderer.
+pub trait OutputSink {
+    fn format(&self) -> FrameFormat;
+    fn begin(&mut self, _info: SinkInfo) -> Result<(), OutputSinkError> {
+        Ok(())
+    }
+    fn write_frame(&mut self, frame_indx: usize, frame: Frame) -> Result<(), OutputSinkError>;
+    fn finish(&mut self) -> Result<(), OutputSinkError> {
+        Ok(())
+    }
+}
+
+/// Writes the raw H.264 stream into any `std::io::Write`, such as a `File` or a `Vec<u8>`.
+pub struct WriteSink<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> WriteSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl WriteSink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, OutputSinkError> {
+        Ok(Self::new(create_file(path.as_ref())?))
+    }
+}
+
+impl<W: Write> OutputSink for WriteSink<W> {
+    fn format(&self) -> FrameFormat {
+        FrameFormat::H264
+    }
+    fn write_frame(&mut self, frame_indx: usize, frame: Frame) -> Result<(), OutputSinkError> {
+        let Frame::H264(bytes) = frame else {
+            return Err(Report::new(OutputSinkError))
+                .attach_printable("WriteSink can only write H.264 frames");
+        };
+        self.writer
+            .write_all(bytes)
+            .into_report()
+            .change_context(OutputSinkError)
+            .attach_printable_lazy(|| format!("Failed to write frame {}", frame_indx))
+    }
+    fn finish(&mut self) -> Result<(), OutputSinkError> {
+        self.writer
+            .flush()
+            .into_report()
+            .change_context(OutputSinkError)
+            .attach_printable_lazy(|| "Failed to flush writer")
+    }
+}
+
+/// Muxes the H.264 stream into an mp4 file with [`Mp4Muxer`], written out once the last frame arrives.
+pub struct Mp4Sink<W: Write> {
+    writer: W,
+    muxer: Option<Mp4Muxer>,
+}
+
+impl<W: Write> Mp4Sink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            muxer: None,
+        }
+    }
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl Mp4Sink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, OutputSinkError> {
+        Ok(Self::new(create_file(path.as_ref())?))
+    }
+}
+
+impl<W: Write> OutputSink for Mp4Sink<W> {
+    fn format(&self) -> FrameFormat {
+        FrameFormat::H264
+    }
+    fn begin(&mut self, info: SinkInfo) -> Result<(), OutputSinkError> {
+        self.muxer = Some(Mp4Muxer::new(
+            info.resolution.x as u32,
+            info.resolution.y as u32,
+            info.frame_rate.numerator,
+            info.frame_rate.denominator,
+        ));
+        Ok(())
+    }
+    fn write_frame(&mut self, _frame_indx: usize, frame: Frame) -> Result<(), OutputSinkError> {
+        match (self.muxer.as_mut(), frame) {
+            (Some(muxer), Frame::H264(bytes)) => {
+                muxer.push_frame(bytes);
+                Ok(())
+            }
+            (None, _) => Err(Report::new(OutputSinkError))
+                .attach_printable("Mp4Sink received a frame before begin was called"),
+            (_, Frame::Rgba(_)) => Err(Report::new(OutputSinkError))
+                .attach_printable("Mp4Sink can only mux H.264 frames"),
+        }
+    }
+    fn finish(&mut self) -> Result<(), OutputSinkError> {
+        self.muxer
+            .take()
+            .ok_or(Report::new(OutputSinkError))
+            .attach_printable("Mp4Sink was finished before begin was called")?
+            .finish(&mut self.writer)
+            .change_context(OutputSinkError)?;
+        self.writer
+            .flush()
+            .into_report()
+            .change_context(OutputSinkError)
+            .attach_printable_lazy(|| "Failed to flush writer")
+    }
+}
+
+/// Keeps every rendered RGBA frame in memory, useful for tests and further processing.
+#[derive(Default)]
+pub struct RgbaFramesSink {
+    pub frames: Vec<RgbaImage>,
+}
+
+impl RgbaFramesSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl OutputSink for RgbaFramesSink {
+    fn format(&self) -> FrameFormat {
+        FrameFormat::Rgba
+    }
+    fn begin(&mut self, info: SinkInfo) -> Result<(), OutputSinkError> {
+        self.frames.reserve(info.frame_count);
+        Ok(())
+    }
+    fn write_frame(&mut self, _frame_indx: usize, frame: Frame) -> Result<(), OutputSinkError> {
+        let Frame::Rgba(image) = frame else {
+            return Err(Report::new(OutputSinkError))
+                .attach_printable("RgbaFramesSink can only store RGBA frames");
+        };
+        self.frames.push(image.clone());
+        Ok(())
+    }
+}
+
+/// Saves every frame as a numbered image (`frame_00000.png`, `frame_00001.png`, ...) in a directory.
+///
+/// PNG (the default) keeps the alpha channel produced by the compositor, as do most other formats (PNM frames are saved as `.pam` so they can).
+/// JPEG can't store alpha, so it gets stripped, and Farbfeld and OpenEXR frames are converted to the 16 bit and float colours they need.
+/// Formats the image crate can't encode in this build (WebP and AVIF by default) are rejected when rendering begins.
+pub struct ImageSequenceSink {
+    directory: PathBuf,
+    format: ImageFormat,
+    prefix: String,
+}
+
+impl ImageSequenceSink {
+    pub fn new<P: AsRef<Path>>(directory: P) -> Self {
+        Self {
+            directory: directory.as_ref().to_path_buf(),
+            format: ImageFormat::Png,
+            prefix: "frame_".to_owned(),
+        }
+    }
+    pub fn with_format(mut self, format: ImageFormat) -> Self {
+        self.format = format;
+        self
+    }
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_owned();
+        self
+    }
+    pub fn frame_path(&self, frame_indx: usize) -> PathBuf {
+        self.directory.join(format!(
+            "{}{:05}.{}",
+            self.prefix,
+            frame_indx,
+            match self.format {
+                ImageFormat::Pnm => "pam",
+                _ => self.format.extensions_str()[0],
+            }
+        ))
+    }
+    fn storable(&self, image: &RgbaImage) -> DynamicImage {
+        //! Converts a frame to a colour type the format can store
+        match self.format {
+            ImageFormat::Jpeg => DynamicImage::ImageRgb8(rgba_to_rgb(image.clone())),
+            ImageFormat::Farbfeld => DynamicImage::ImageRgba8(image.clone()).into_rgba16().into(),
+            ImageFormat::OpenExr => DynamicImage::ImageRgba8(image.clone())
+                .into_rgba32f()
+                .into(),
+            _ => DynamicImage::ImageRgba8(image.clone()),
+        }
+    }
+}
+
+impl OutputSink for ImageSequenceSink {
+    fn format(&self) -> FrameFormat {
+        FrameFormat::Rgba
+    }
+    fn begin(&mut self, _info: SinkInfo) -> Result<(), OutputSinkError> {
+        //Saving a tiny frame catches formats that can't be written before any rendering happens
+        self.storable(&RgbaImage::new(1, 1))
+            .write_to(
+                &mut Cursor::new(Vec::new()),
+                ImageOutputFormat::from(self.format),
+            )
+            .into_report()
+            .change_context(OutputSinkError)
+            .attach_printable_lazy(|| format!("Can't write images as {:?}", self.format))?;
+        DirBuilder::new()
+            .recursive(true)
+            .create(&self.directory)
+            .into_report()
+            .change_context(OutputSinkError)
+            .attach_printable_lazy(|| {
+                format!("Failed to create directory {}", self.directory.display())
+            })
+    }
+    fn write_frame(&mut self, frame_indx: usize, frame: Frame) -> Result<(), OutputSinkError> {
+        let Frame::Rgba(image) = frame else {
+            return Err(Report::new(OutputSinkError))
+                .attach_printable("ImageSequenceSink can only save RGBA frames");
+        };
+        let path = self.frame_path(frame_indx);
+        self.storable(image)
+            .write_to(
+                &mut create_file(&path)?,
+                ImageOutputFormat::from(self.format),
+            )
+            .into_report()
+            .change_context(OutputSinkError)
+            .attach_printable_lazy(|| format!("Failed to save frame to {}", path.display()))
+    }
+}
+
+/// Encodes the frames as a looping animated GIF, quantising each frame to its own 255 colour palette.
+///
+/// Pixels with less than half opacity become fully transparent, as GIF has no partial transparency.
+pub struct GifSink<W: Write> {
+    writer: Option<W>,
+    encoder: Option<gif::Encoder<W>>,
+    frame_rate: FrameRate,
+    dithering: bool,
+    speed: i32,
+}
+
+impl<W: Write> GifSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Some(writer),
+            encoder: None,
+            frame_rate: FrameRate::new(1, 1),
+            dithering: true,
+            speed: 10,
+        }
+    }
+    pub fn with_dithering(mut self, dithering: bool) -> Self {
+        //! Floyd-Steinberg dithering hides banding in gradients, at the cost of noisier flat areas. On by default.
+        self.dithering = dithering;
+        self
+    }
+    pub fn with_speed(mut self, speed: i32) -> Self {
+        //! Quantisation speed from 1 (best palette, slowest) to 30 (fastest), defaults to 10.
+        self.speed = speed.clamp(1, 30);
+        self
+    }
+}
+
+impl GifSink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, OutputSinkError> {
+        Ok(Self::new(create_file(path.as_ref())?))
+    }
+}
+
+impl<W: Write> OutputSink for GifSink<W> {
+    fn format(&self) -> FrameFormat {
+        FrameFormat::Rgba
+    }
+    fn begin(&mut self, info: SinkInfo) -> Result<(), OutputSinkError> {
+        let (width, height) = match (
+            u16::try_from(info.resolution.x),
+            u16::try_from(info.resolution.y),
+        ) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => {
+                return Err(Report::new(OutputSinkError))
+                    .attach_printable("GIFs can't be larger than 65535x65535")
+            }
+        };
+        let writer = self
+            .writer
+            .take()
+            .ok_or(Report::new(OutputSinkError))
+            .attach_printable("GifSink can only be used for one render")?;
+        let mut encoder = gif::Encoder::new(writer, width, height, &[])
+            .into_report()
+            .change_context(OutputSinkError)
+            .attach_printable_lazy(|| "Failed to write gif header")?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .into_report()
+            .change_context(OutputSinkError)?;
+        self.encoder = Some(encoder);
+        self.frame_rate = info.frame_rate;
+        Ok(())
+    }
+    fn write_frame(&mut self, frame_indx: usize, frame: Frame) -> Result<(), OutputSinkError> {
+        let Frame::Rgba(image) = frame else {
+            return Err(Report::new(OutputSinkError))
+                .attach_printable("GifSink can only encode RGBA frames");
+        };
+        let encoder = self
+            .encoder
+            .as_mut()
+            .ok_or(Report::new(OutputSinkError))
+            .attach_printable("GifSink received a frame before begin was called")?;
+
+        let mut image = image.clone();
+        let quantizer = NeuQuant::new(self.speed, 255, image.as_raw());
+        if self.dithering {
+            image::imageops::dither(&mut image, &quantizer);
+        }
+        let transparent = 255;
+        let mut palette = quantizer.color_map_rgb();
+        palette.extend_from_slice(&[0, 0, 0]);
+        let indices = image
+            .pixels()
+            .map(|p| match p.0[3] < 128 {
+                true => transparent,
+                false => quantizer.index_of(&p.0) as u8,
+            })
+            .collect::<Vec<u8>>();
+
+        // GIF delays are whole centiseconds, so round the timestamps rather than every delay to keep the overall speed right
+        let centis =
+            |i: usize| (self.frame_rate.frame_time(i).as_secs_f64() * 100.0).round() as u64;
+
+        encoder
+            .write_frame(&gif::Frame {
+                width: image.width() as u16,
+                height: image.height() as u16,
+                delay: (centis(frame_indx + 1) - centis(frame_indx)) as u16,
+                dispose: gif::DisposalMethod::Background,
+                transparent: Some(transparent),
+                palette: Some(palette),
+                buffer: Cow::Owned(indices),
+                ..Default::default()
+            })
+            .into_report()
+            .change_context(OutputSinkError)
+            .attach_printable_lazy(|| format!("Failed to encode frame {}", frame_indx))
+    }
+    fn finish(&mut self) -> Result<(), OutputSinkError> {
+        self.encoder
+            .take()
+            .ok_or(Report::new(OutputSinkError))
+            .attach_printable("GifSink was finished before begin was called")?
+            .into_inner()
+            .and_then(|mut writer| writer.flush())
+            .into_report()
+            .change_context(OutputSinkError)
+            .attach_printable_lazy(|| "Failed to finish gif")
+    }
+}
+
+/// Encodes the frames as a looping, lossless animated PNG, alpha channel included.
+pub struct ApngSink<W: Write> {
+    writer: Option<W>,
+    encoder: Option<png::Writer<W>>,
+}
+
+impl<W: Write> ApngSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Some(writer),
+            encoder: None,
+        }
+    }
+}
+
+impl ApngSink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, OutputSinkError> {
+        Ok(Self::new(create_file(path.as_ref())?))
+    }
+}
+
+impl<W: Write> OutputSink for ApngSink<W> {
+    fn format(&self) -> FrameFormat {
+        FrameFormat::Rgba
+    }
+    fn begin(&mut self, info: SinkInfo) -> Result<(), OutputSinkError> {
+        let writer = self
+            .writer
+            .take()
+            .ok_or(Report::new(OutputSinkError))
+            .attach_printable("ApngSink can only be used for one render")?;
+        let mut encoder =
+            png::Encoder::new(writer, info.resolution.x as u32, info.resolution.y as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        // Frame delays are u16 ratios, fall back to whole milliseconds for rates that don't fit.
+        // A delay is seconds per frame, so it's the frame rate flipped over.
+        let delay = match (
+            u16::try_from(info.frame_rate.denominator),
+            u16::try_from(info.frame_rate.numerator),
+        ) {
+            (Ok(delay_num), Ok(delay_den)) => (delay_num, delay_den),
+            _ => ((1000.0 / info.frame_rate.as_f64()).round() as u16, 1000),
+        };
+        encoder
+            .set_animated(info.frame_count as u32, 0)
+            .and_then(|_| encoder.set_frame_delay(delay.0, delay.1))
+            .into_report()
+            .change_context(OutputSinkError)
+            .attach_printable_lazy(|| "Failed to set up apng animation")?;
+        self.encoder = Some(
+            encoder
+                .write_header()
+                .into_report()
+                .change_context(OutputSinkError)
+                .attach_printable_lazy(|| "Failed to write png header")?,
+        );
+        Ok(())
+    }
+    fn write_frame(&mut self, frame_indx: usize, frame: Frame) -> Result<(), OutputSinkError> {
+        let Frame::Rgba(image) = frame else {
+            return Err(Report::new(OutputSinkError))
+                .attach_printable("ApngSink can only encode RGBA frames");
+        };
+        self.encoder
+            .as_mut()
+            .ok_or(Report::new(OutputSinkError))
+            .attach_printable("ApngSink received a frame before begin was called")?
+            .write_image_data(image.as_raw())
+            .into_report()
+            .change_context(OutputSinkError)
+            .attach_printable_lazy(|| format!("Failed to encode frame {}", frame_indx))
+    }
+    fn finish(&mut self) -> Result<(), OutputSinkError> {
+        self.encoder
+            .take()
+            .ok_or(Report::new(OutputSinkError))
+            .attach_printable("ApngSink was finished before begin was called")?
+            .finish()
+            .into_report()
+            .change_context(OutputSinkError)
+            .attach_printable_lazy(|| "Failed to finish apng")
+    }
+}
@@ -0,0 +1,231 @@
+use crate::prelude::*;
+use dyn_clone::{clone_trait_object, DynClone};
+use rayon::prelude::*;
+use std::time::Duration;
+
+/// A stage that gets the finished frame after all renderables have been drawn, and is free to modify it however it wants.
+/// Stages run in the order they were added to the scene.
+pub trait PostProcess: DynClone + Send + Sync {
+    fn process(&self, frame: &mut Img, time: Duration);
+}
+clone_trait_object!(PostProcess);
+
+fn map_pixels<F: Fn(usize, usize, Rgba<u8>) -> Rgba<u8> + Sync>(frame: &mut Img, func: F) {
+    //! Runs func(x, y, pixel) over every pixel, in parallel across rows
+    let width = frame.dimensions.x;
+    if width == 0 {
+        return;
+    }
+    frame
+        .image
+        .par_chunks_mut(width * 4)
+        .enumerate()
+        .for_each(|(y, row)| {
+            row.chunks_mut(4).enumerate().for_each(|(x, pixel)| {
+                let new = func(x, y, Rgba([pixel[0], pixel[1], pixel[2], pixel[3]]));
+                pixel.copy_from_slice(&new.0);
+            })
+        });
+}
+
+fn to_u8(v: f64) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Basic colour correction, the default changes nothing.
+/// Ex:
+/// ```
+/// use shanimation_rs::prelude::*;
+///
+/// ColorGrade {
+///     contrast: 1.2,
+///     saturation: 0.8,
+///     ..Default::default()
+/// };
+///```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorGrade {
+    /// In stops, each one doubles the brightness
+    pub exposure: f64,
+    /// Scales the distance from middle grey
+    pub contrast: f64,
+    /// 0.0 is greyscale
+    pub saturation: f64,
+    /// Above 1.0 brightens the midtones, below darkens them
+    pub gamma: f64,
+}
+
+impl Default for ColorGrade {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+impl PostProcess for ColorGrade {
+    fn process(&self, frame: &mut Img, _time: Duration) {
+        let gain = 2f64.powf(self.exposure);
+        let gamma = 1.0 / self.gamma.max(f64::EPSILON);
+        map_pixels(frame, |_, _, pixel| {
+            let rgb = [0, 1, 2].map(|i| {
+                let v = pixel.0[i] as f64 / 255.0 * gain;
+                (v - 0.5) * self.contrast + 0.5
+            });
+            let luma = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+            let [r, g, b] =
+                rgb.map(|v| to_u8((luma + (v - luma) * self.saturation).max(0.0).powf(gamma)));
+            Rgba([r, g, b, pixel.0[3]])
+        });
+    }
+}
+
+/// Darkens the frame towards its edges
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vignette {
+    /// How dark the edges get, 1.0 being black
+    pub strength: f64,
+    /// Where the darkening starts, 1.0 being the middle of the frame's edges
+    pub radius: f64,
+    /// How far past the radius it takes to reach full strength
+    pub softness: f64,
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Self {
+            strength: 0.5,
+            radius: 0.75,
+            softness: 0.6,
+        }
+    }
+}
+
+impl PostProcess for Vignette {
+    fn process(&self, frame: &mut Img, _time: Duration) {
+        let dimensions = frame.dimensions.map_both(|v| v as f64);
+        map_pixels(frame, |x, y, pixel| {
+            let distance = Point::new(
+                ((x as f64 + 0.5) / dimensions.x - 0.5) * 2.0,
+                ((y as f64 + 0.5) / dimensions.y - 0.5) * 2.0,
+            );
+            let distance = distance.x.hypot(distance.y);
+            let t = ((distance - self.radius) / self.softness.max(f64::EPSILON)).clamp(0.0, 1.0);
+            let factor = 1.0 - self.strength * t * t * (3.0 - 2.0 * t); //smoothstep
+            let [r, g, b] = [0, 1, 2].map(|i| to_u8(pixel.0[i] as f64 / 255.0 * factor));
+            Rgba([r, g, b, pixel.0[3]])
+        });
+    }
+}
+
+/// Monochrome noise that changes every frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilmGrain {
+    /// The most a channel can be pushed up or down, 1.0 being the full range
+    pub strength: f64,
+    /// Changes the noise pattern, so layered grain doesn't line up
+    pub seed: u64,
+}
+
+impl Default for FilmGrain {
+    fn default() -> Self {
+        Self {
+            strength: 0.05,
+            seed: 0,
+        }
+    }
+}
+
+impl FilmGrain {
+    fn noise(&self, x: usize, y: usize, time: Duration) -> f64 {
+        //! SplitMix64 style hash of the pixel and time, mapped to -1.0..1.0
+        let mut v = self.seed
+            ^ (x as u64).wrapping_mul(0x9E3779B97F4A7C15)
+            ^ (y as u64).wrapping_mul(0xC2B2AE3D27D4EB4F)
+            ^ (time.as_nanos() as u64).wrapping_mul(0x165667B19E3779F9);
+        v = (v ^ (v >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        v = (v ^ (v >> 27)).wrapping_mul(0x94D049BB133111EB);
+        v ^= v >> 31;
+        (v >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+    }
+}
+
+impl PostProcess for FilmGrain {
+    fn process(&self, frame: &mut Img, time: Duration) {
+        map_pixels(frame, |x, y, pixel| {
+            let grain = self.noise(x, y, time) * self.strength;
+            let [r, g, b] = [0, 1, 2].map(|i| to_u8(pixel.0[i] as f64 / 255.0 + grain));
+            Rgba([r, g, b, pixel.0[3]])
+        });
+    }
+}
+
+/// Covers the frame down to an aspect ratio (width / height), with bars on the top and bottom, or on the sides if the frame is too tall
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Letterbox {
+    pub aspect_ratio: f64,
+    pub color: Rgba<u8>,
+}
+
+impl Letterbox {
+    pub fn new(aspect_ratio: f64) -> Self {
+        //! Black bars
+        Self {
+            aspect_ratio,
+            color: Rgba([0, 0, 0, 255]),
+        }
+    }
+}
+
+impl PostProcess for Letterbox {
+    fn process(&self, frame: &mut Img, _time: Duration) {
+        let dimensions = frame.dimensions.map_both(|v| v as f64);
+        let visible = if dimensions.x / dimensions.y > self.aspect_ratio {
+            Point::new(dimensions.y * self.aspect_ratio, dimensions.y)
+        } else {
+            Point::new(dimensions.x, dimensions.x / self.aspect_ratio)
+        };
+        let up_left = ((dimensions - visible) / 2.0).map_both(|v| v.round() as usize);
+        let down_right = ((dimensions + visible) / 2.0).map_both(|v| v.round() as usize);
+        map_pixels(frame, |x, y, pixel| {
+            if x < up_left.x || y < up_left.y || x >= down_right.x || y >= down_right.y {
+                self.color
+            } else {
+                pixel
+            }
+        });
+    }
+}
+
+/// Ex:
+/// ```
+/// use shanimation_rs::prelude::*;
+///
+/// ClosurePostProcess {
+///     process: |frame: &mut Img, _time| {
+///         //invert the colours
+///         frame.image.pixels_mut().for_each(|p| {
+///             p.0 = [255 - p.0[0], 255 - p.0[1], 255 - p.0[2], p.0[3]]
+///         });
+///     },
+/// };
+///```
+#[derive(Clone)]
+pub struct ClosurePostProcess<F>
+where
+    F: Fn(&mut Img, Duration) + Clone + Send + Sync,
+{
+    pub process: F,
+}
+
+impl<F> PostProcess for ClosurePostProcess<F>
+where
+    F: Fn(&mut Img, Duration) + Clone + Send + Sync,
+{
+    fn process(&self, frame: &mut Img, time: Duration) {
+        (self.process)(frame, time)
+    }
+}
@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+#[cfg(feature = "terminal-progress")]
+use crossterm::{
+    cursor::{Hide, MoveToPreviousLine},
+    execute,
+    style::{Color, Print, ResetColor, SetForegroundColor},
+    terminal::{BeginSynchronizedUpdate, EndSynchronizedUpdate},
+};
+#[cfg(feature = "terminal-progress")]
+use std::sync::Mutex;
+
+/// Something that happened while rendering a scene, see [`RenderProgress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// Behaviours have been run for `done` out of `total` frames.
+    FramesProcessed { done: usize, total: usize },
+    /// `done` out of `total` frames have been composited. Reported from the rendering threads.
+    FramesRendered { done: usize, total: usize },
+    /// `done` out of `total` frames have been encoded and handed to the output sink.
+    FramesEncoded { done: usize, total: usize },
+    /// Estimated time left until every frame is encoded.
+    Eta(Duration),
+    /// The output sink has been finished, the render is done.
+    Finished,
+}
+
+/// Observes a render, set with `SceneBuilder::with_progress`. Implemented for any `Fn(ProgressEvent)`.
+pub trait RenderProgress: Send + Sync {
+    fn report(&self, event: ProgressEvent);
+}
+
+impl<F: Fn(ProgressEvent) + Send + Sync> RenderProgress for F {
+    fn report(&self, event: ProgressEvent) {
+        self(event)
+    }
+}
+
+#[cfg(feature = "terminal-progress")]
+#[derive(Default)]
+struct TerminalProgressState {
+    rendered: usize,
+    eta: Duration,
+}
+
+/// Draws a coloured progress line in the terminal with crossterm.
+#[cfg(feature = "terminal-progress")]
+#[derive(Default)]
+pub struct TerminalProgress {
+    state: Mutex<TerminalProgressState>,
+}
+
+#[cfg(feature = "terminal-progress")]
+impl TerminalProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(feature = "terminal-progress")]
+impl RenderProgress for TerminalProgress {
+    fn report(&self, event: ProgressEvent) {
+        let mut state = self.state.lock().unwrap();
+        let percent = |done: usize, total: usize| (done as f64 / total as f64) * 100.0;
+        // Progress is best effort, a terminal that can't be drawn to shouldn't stop a render
+        let _ = match event {
+            ProgressEvent::FramesProcessed { done, total } => {
+                if done == 1 {
+                    println!("Rendering video...\n");
+                }
+                execute!(
+                    std::io::stdout(),
+                    BeginSynchronizedUpdate,
+                    Hide,
+                    MoveToPreviousLine(1),
+                    SetForegroundColor(Color::Blue),
+                    Print("Runnning Behaviours"),
+                    ResetColor,
+                    Print(format!(" | Processed {} / {} frames", done, total)),
+                    SetForegroundColor(Color::Blue),
+                    Print(format!(" ({:.0}%)", percent(done, total))),
+                    ResetColor,
+                    Print(" | ".to_owned()),
+                    ResetColor,
+                    Print("\n".to_owned()),
+                    EndSynchronizedUpdate,
+                )
+            }
+            ProgressEvent::FramesRendered { done, .. } => {
+                state.rendered = state.rendered.max(done);
+                Ok(())
+            }
+            ProgressEvent::Eta(eta) => {
+                state.eta = eta;
+                Ok(())
+            }
+            ProgressEvent::FramesEncoded { done, total } if state.rendered >= total => execute!(
+                std::io::stdout(),
+                BeginSynchronizedUpdate,
+                Hide,
+                MoveToPreviousLine(1),
+                SetForegroundColor(Color::Blue),
+                Print("Finished rendering frames, still encoding video"),
+                ResetColor,
+                Print(format!(" | Encoded {} / {} frames", done, total)),
+                SetForegroundColor(Color::Blue),
+                Print(format!(" ({:.0}%)", percent(done, total))),
+                ResetColor,
+                Print(" | ".to_owned()),
+                SetForegroundColor(Color::Green),
+                Print(format!(
+                    "ETA: {}:{}",
+                    state.eta.as_secs() / 60,
+                    state.eta.as_secs() % 60
+                )),
+                ResetColor,
+                Print("\n".to_owned()),
+                EndSynchronizedUpdate,
+            ),
+            ProgressEvent::FramesEncoded { done, total } => execute!(
+                std::io::stdout(),
+                BeginSynchronizedUpdate,
+                Hide,
+                MoveToPreviousLine(1),
+                Print(format!("Rendered frame {}/{}", state.rendered, total)),
+                SetForegroundColor(Color::Blue),
+                Print(format!(" ({:.0}%)", percent(state.rendered, total))),
+                ResetColor,
+                Print(format!(" | Encoded {} / {} frames", done, total)),
+                SetForegroundColor(Color::Blue),
+                Print(format!(" ({:.0}%)", percent(done, total))),
+                ResetColor,
+                Print(" | ".to_owned()),
+                SetForegroundColor(Color::Green),
+                Print(format!(
+                    "ETA: {}:{}",
+                    state.eta.as_secs() / 60,
+                    state.eta.as_secs() % 60
+                )),
+                ResetColor,
+                Print("\n".to_owned()),
+                EndSynchronizedUpdate,
+            ),
+            ProgressEvent::Finished => Ok(()),
+        };
+    }
+}
@@ -0,0 +1,72 @@
+use crate::prelude::*;
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatteMode {
+    /// Shows the target where the matte is opaque
+    Alpha,
+    /// Shows the target where the matte is transparent
+    AlphaInverted,
+    /// Shows the target where the matte is bright
+    Luma,
+    /// Shows the target where the matte is dark
+    LumaInverted,
+}
+
+impl MatteMode {
+    pub fn amount(self, pixel: Rgba<u8>) -> f64 {
+        //! How much of the target shows through (0.0 to 1.0) where the matte has rendered `pixel`
+        let alpha = pixel.0[3] as f64 / 255.0;
+        //Rec. 709 luma, over transparent black
+        let luma = || {
+            (0.2126 * pixel.0[0] as f64 + 0.7152 * pixel.0[1] as f64 + 0.0722 * pixel.0[2] as f64)
+                / 255.0
+                * alpha
+        };
+        match self {
+            MatteMode::Alpha => alpha,
+            MatteMode::AlphaInverted => 1.0 - alpha,
+            MatteMode::Luma => luma(),
+            MatteMode::LumaInverted => 1.0 - luma(),
+        }
+    }
+}
+
+/// A track matte, like in After Effects. The source is rendered offscreen in the same space as the renderable it's attached to (so positioned relative to that renderable's parent),
+/// and is used to cut out that renderable and all of its children. The source itself never shows up in the frame.
+///
+/// The source belongs to the matte alone, and gets its behaviour run and is cloned along with the renderable it's attached to,
+/// so it can't be a renderable that's also in the scene. To cut out a renderable's children with the renderable itself, see ChildrenMatte.
+#[derive(Clone)]
+pub struct Matte {
+    pub source: Arc<RwLock<Renderable>>,
+    pub mode: MatteMode,
+}
+
+impl Matte {
+    pub fn new(source: Renderable, mode: MatteMode) -> Self {
+        Self {
+            source: Arc::new(RwLock::new(source)),
+            mode,
+        }
+    }
+    pub fn amount(&self, pixel: Rgba<u8>) -> f64 {
+        //! How much of the target shows through (0.0 to 1.0) where the matte has rendered `pixel`
+        self.mode.amount(pixel)
+    }
+    pub fn clone_entire(&self) -> Self {
+        Self {
+            source: Arc::new(RwLock::new(self.source.read().unwrap().clone_entire())),
+            mode: self.mode,
+        }
+    }
+}
+
+/// Uses a renderable's own layer (without its children) as a matte for all of its children, like a clipping mask.
+/// The children are cut out together and composited over the renderable as one layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChildrenMatte {
+    pub mode: MatteMode,
+    /// Whether the renderable still draws itself, or is only used to cut out its children
+    pub show_self: bool,
+}
@@ -5,6 +5,8 @@ use std::time::Duration;
 
 pub mod closure_behaviour;
 pub mod finite_state_machine;
+pub mod matte;
+pub mod scene_behaviour;
 
 pub trait Behaviour: DynClone + Send + Sync {
     fn process(
@@ -33,10 +35,36 @@ pub struct Renderable {
 #[derive(Clone)]
 pub struct RenderableParams {
     children: Vec<Arc<RwLock<Renderable>>>,
+    /// Scales the renderable and its children around the anchor. With the default centre anchor a scaled renderable stays centred where it was,
+    /// unlike older versions which scaled from the top left corner. Set the anchor to (0, 0) for the old behaviour.
     pub scale: Point<f64>,
     pub position: Point<f64>,
     pub size: Point<f64>,
+    /// Counter-clockwise, in radians, around the anchor
     pub rotation: f64,
+    /// The point rotation, skew and scale happen around, as a fraction of size (0, 0 being the top left).
+    /// Defaults to the centre, which keeps rotations where they were but moves scaled renderables (and their children) compared to older versions, see scale.
+    pub anchor: Point<f64>,
+    /// Shear angles in radians, applied around the anchor
+    pub skew: Point<f64>,
+    pub blend_mode: BlendMode,
+    /// Siblings are drawn from the lowest z_index up, siblings with the same z_index are drawn in the order they were added.
+    /// A renderable is always drawn before (underneath) its own children. Can be changed from Behaviour::process to reorder over time.
+    pub z_index: i32,
+    /// Hidden renderables (and their children) are skipped when compositing, but their behaviours are still processed
+    pub visible: bool,
+    /// 0.0 to 1.0, multiplied down onto children
+    pub opacity: f64,
+    /// When the renderable (and its children) starts being drawn, None being the start of the scene
+    pub in_point: Option<Duration>,
+    /// When the renderable (and its children) stops being drawn, None being the end of the scene
+    pub out_point: Option<Duration>,
+    /// Cuts out this renderable and its children, see Matte
+    pub matte: Option<Matte>,
+    /// Cuts out the children with this renderable, see ChildrenMatte
+    pub children_matte: Option<ChildrenMatte>,
+    /// Overides the scene's supersampling for this renderable (but not its children)
+    pub supersampling: Option<usize>,
 }
 
 impl RenderableParams {
@@ -55,6 +83,32 @@ impl RenderableParams {
     pub fn get_children_mut(&mut self) -> &mut Vec<Arc<RwLock<Renderable>>> {
         &mut self.children
     }
+    pub fn is_shown(&self, time: Duration) -> bool {
+        //! Whether the renderable gets drawn at `time`, from its visibility and in/out points
+        self.visible
+            && self.in_point.is_none_or(|in_point| time >= in_point)
+            && self.out_point.is_none_or(|out_point| time < out_point)
+    }
+    pub fn pixel_size(&self, resolution: Point<usize>) -> Point<f64> {
+        //! The unscaled size in pixels, size is relative to the scene's height on both axis.
+        self.size * resolution.y as f64
+    }
+    pub fn local_transform(&self, resolution: Point<usize>) -> Transform {
+        //! Maps from this renderable's pixel space (0, 0 being its top left corner) into its parent's.
+        let anchor = Point::new(
+            self.anchor.x * self.pixel_size(resolution).x,
+            self.anchor.y * self.pixel_size(resolution).y,
+        );
+        let position = Point::new(
+            self.position.x * resolution.x as f64,
+            self.position.y * resolution.y as f64,
+        );
+        Transform::translation(position + anchor)
+            * Transform::rotation(self.rotation)
+            * Transform::skew(self.skew)
+            * Transform::scale(self.scale)
+            * Transform::translation(anchor * -1.0)
+    }
 }
 
 impl Renderable {
@@ -68,6 +122,7 @@ impl Renderable {
             .collect::<Vec<_>>();
         let mut new_self = self.clone();
         new_self.params.children = children;
+        new_self.params.matte = self.params.matte.as_ref().map(Matte::clone_entire);
         new_self
     }
     pub fn add_child(&mut self, child: Arc<RwLock<Renderable>>) {
@@ -84,7 +139,7 @@ impl Renderable {
         self.params.get_children_mut()
     }
     pub fn run_shader(
-        &mut self,
+        &self,
         current_frame: &Img,
         uv_coords: Point<f64>,
         time: Duration,
@@ -105,6 +160,17 @@ impl Renderable {
             position: Some(Point::new(0.0, 0.0)),
             size: Some(Point::new(1.0, 1.0)),
             behaviour: None,
+            anchor: Point::new(0.5, 0.5),
+            skew: Point::new(0.0, 0.0),
+            blend_mode: BlendMode::Normal,
+            z_index: 0,
+            visible: true,
+            opacity: 1.0,
+            in_point: None,
+            out_point: None,
+            matte: None,
+            children_matte: None,
+            supersampling: None,
         }
     }
 }
@@ -120,6 +186,17 @@ pub struct RenderableBuilder {
     size: Option<Point<f64>>,
     rotation: f64,
     behaviour: Option<Box<dyn Behaviour>>,
+    anchor: Point<f64>,
+    skew: Point<f64>,
+    blend_mode: BlendMode,
+    z_index: i32,
+    visible: bool,
+    opacity: f64,
+    in_point: Option<Duration>,
+    out_point: Option<Duration>,
+    matte: Option<Matte>,
+    children_matte: Option<ChildrenMatte>,
+    supersampling: Option<usize>,
 }
 
 impl RenderableBuilder {
@@ -147,6 +224,52 @@ impl RenderableBuilder {
         self.rotation = rotation;
         self
     }
+    pub fn with_anchor(&mut self, x: f64, y: f64) -> &mut Self {
+        //! Defaults to (0.5, 0.5), use (0.0, 0.0) to scale from the top left corner like older versions did
+        self.anchor = Point::new(x, y);
+        self
+    }
+    pub fn with_skew(&mut self, x: f64, y: f64) -> &mut Self {
+        self.skew = Point::new(x, y);
+        self
+    }
+    pub fn with_blend_mode(&mut self, blend_mode: BlendMode) -> &mut Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+    pub fn with_z_index(&mut self, z_index: i32) -> &mut Self {
+        self.z_index = z_index;
+        self
+    }
+    pub fn with_visibility(&mut self, visible: bool) -> &mut Self {
+        self.visible = visible;
+        self
+    }
+    pub fn with_opacity(&mut self, opacity: f64) -> &mut Self {
+        self.opacity = opacity;
+        self
+    }
+    pub fn with_in_point(&mut self, in_point: Duration) -> &mut Self {
+        self.in_point = Some(in_point);
+        self
+    }
+    pub fn with_out_point(&mut self, out_point: Duration) -> &mut Self {
+        self.out_point = Some(out_point);
+        self
+    }
+    pub fn with_matte(&mut self, source: Renderable, mode: MatteMode) -> &mut Self {
+        self.matte = Some(Matte::new(source, mode));
+        self
+    }
+    pub fn with_children_matte(&mut self, mode: MatteMode, show_self: bool) -> &mut Self {
+        //! Cuts out the children with this renderable's own layer, drawing the renderable too if `show_self`
+        self.children_matte = Some(ChildrenMatte { mode, show_self });
+        self
+    }
+    pub fn with_supersampling(&mut self, samples: usize) -> &mut Self {
+        self.supersampling = Some(samples.max(1));
+        self
+    }
     pub fn build(&mut self) -> Result<Renderable, RenderableBuilderError> {
         let mut err = false;
         let mut report = Err(Report::new(RenderableBuilderError));
@@ -191,10 +314,21 @@ impl RenderableBuilder {
         Ok(Renderable {
             params: RenderableParams {
                 rotation: self.rotation,
+                anchor: self.anchor,
+                skew: self.skew,
                 children: std::mem::replace(&mut self.children, vec![]),
                 scale: self.scale,
                 position: self.position.unwrap(),
                 size: self.size.unwrap(),
+                blend_mode: self.blend_mode,
+                z_index: self.z_index,
+                visible: self.visible,
+                opacity: self.opacity,
+                in_point: self.in_point,
+                out_point: self.out_point,
+                matte: self.matte.take(),
+                children_matte: self.children_matte,
+                supersampling: self.supersampling,
             },
             behaviour: std::mem::replace(&mut self.behaviour, Some(Box::new(DummyBehaviour)))
                 .unwrap(),
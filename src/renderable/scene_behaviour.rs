@@ -0,0 +1,140 @@
+use crate::prelude::*;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A pre-composition: plays a whole scene (with its own resolution and fps) inside a renderable, sampling its frames as a texture.
+/// The inner scene's behaviours are stepped through each of its own frames, so the same scene can be reused at different times and scales.
+/// Nothing is drawn before the time offset or after the inner scene's length.
+/// Ex:
+/// ```
+/// use shanimation_rs::prelude::*;
+/// use std::time::Duration;
+///
+/// fn place_lower_third(lower_third: Scene) -> SceneBehaviour {
+///     SceneBehaviour::new(lower_third)
+///         .with_time_offset(Duration::from_secs(2))
+///         .with_time_remap(|time| time * 2) //play at double speed
+/// }
+///```
+pub struct SceneBehaviour {
+    initial: Arc<Scene>,
+    scene: Scene,
+    time_offset: Duration,
+    time_remap: Option<Arc<dyn Fn(Duration) -> Duration + Send + Sync>>,
+    next_frame: usize,
+    frame: Option<(usize, Arc<Img>)>,
+    showing: bool,
+}
+
+impl Clone for SceneBehaviour {
+    fn clone(&self) -> Self {
+        //The inner scene gets stepped by process, so clones can't share its renderables
+        Self {
+            initial: self.initial.clone(),
+            scene: self.scene.clone_entire(),
+            time_offset: self.time_offset,
+            time_remap: self.time_remap.clone(),
+            next_frame: self.next_frame,
+            frame: self.frame.clone(),
+            showing: self.showing,
+        }
+    }
+}
+
+impl SceneBehaviour {
+    pub fn new(scene: Scene) -> Self {
+        Self {
+            initial: Arc::new(scene.clone_entire()),
+            scene,
+            time_offset: Duration::ZERO,
+            time_remap: None,
+            next_frame: 0,
+            frame: None,
+            showing: false,
+        }
+    }
+    pub fn with_time_offset(mut self, time_offset: Duration) -> Self {
+        //! When the inner scene starts playing, in the outer scene's time
+        self.time_offset = time_offset;
+        self
+    }
+    pub fn with_time_remap<F: Fn(Duration) -> Duration + Send + Sync + 'static>(
+        mut self,
+        time_remap: F,
+    ) -> Self {
+        //! Maps time since the offset to the inner scene's time. Going backwards is supported, but replays the inner scene from the start.
+        self.time_remap = Some(Arc::new(time_remap));
+        self
+    }
+    fn inner_time(&self, time: Duration) -> Option<Duration> {
+        let local = time.checked_sub(self.time_offset)?;
+        let inner = match &self.time_remap {
+            Some(remap) => remap(local),
+            None => local,
+        };
+        (inner < self.scene.get_length()).then_some(inner)
+    }
+}
+
+impl Behaviour for SceneBehaviour {
+    fn process(
+        &mut self,
+        _renderable: &mut RenderableParams,
+        time: Duration,
+        _scene: &Scene,
+        _abs_position: Point<isize>,
+    ) {
+        let inner_time = match self.inner_time(time) {
+            Some(inner_time) => inner_time,
+            None => {
+                self.showing = false;
+                return;
+            }
+        };
+        self.showing = true;
+        let target_frame = self.scene.get_frame_rate().frame_at(inner_time);
+        if matches!(self.frame, Some((frame_indx, _)) if frame_indx == target_frame) {
+            return;
+        }
+        if target_frame < self.next_frame {
+            //Behaviours can't be run backwards, so start over
+            self.scene = self.initial.clone_entire();
+            self.next_frame = 0;
+        }
+        let frame_rate = self.scene.get_frame_rate();
+        (self.next_frame..=target_frame)
+            .for_each(|frame_indx| self.scene.run_behaviours(frame_rate.frame_time(frame_indx)));
+        self.next_frame = target_frame + 1;
+        let image = match self
+            .scene
+            .render_frame(target_frame, frame_rate.frame_time(target_frame))
+        {
+            Ok(image) => image,
+            Err(_) => {
+                //process can't return errors, so just show nothing rather than aborting the outer render
+                self.frame = None;
+                self.showing = false;
+                return;
+            }
+        };
+        self.frame = Some((
+            target_frame,
+            Arc::new(Img {
+                dimensions: self.scene.get_resolution(),
+                image,
+            }),
+        ));
+    }
+    fn get_pixel(
+        &self,
+        _current_frame: &Img,
+        uv_coords: Point<f64>,
+        _time: Duration,
+        _abs_position: Point<isize>,
+    ) -> Rgba<u8> {
+        match &self.frame {
+            Some((_, frame)) if self.showing => frame.sample(uv_coords),
+            _ => Rgba([0, 0, 0, 0]),
+        }
+    }
+}
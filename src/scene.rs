@@ -1,32 +1,29 @@
 use super::prelude::*;
 use std::fs::DirBuilder;
-use std::io::Write;
 
-use std::ops::{Deref, DerefMut};
+use std::collections::VecDeque;
+use std::ops::DerefMut;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::sync::mpsc::Receiver;
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
 use super::encoding::rgba_to_yuv;
 
 use crate::encoding::RateControlMode;
-use image::RgbaImage;
+use crate::ffmpeg::FFMPEGSink;
+use crate::output::{
+    ApngSink, Frame, FrameFormat, GifSink, ImageSequenceSink, Mp4Sink, OutputSink, SinkInfo,
+};
+use image::{ImageFormat, RgbaImage};
 use openh264::encoder::{Encoder, EncoderConfig};
+use openh264::formats::YUVBuffer;
+use rayon::prelude::*;
 
-use fast_inv_sqrt::InvSqrt64;
-
-use crossterm::{
-    cursor::{Hide, MoveToPreviousLine},
-    execute,
-    style::{Color, Print, ResetColor, SetForegroundColor},
-    terminal::{BeginSynchronizedUpdate, EndSynchronizedUpdate},
-};
+use crate::progress::{ProgressEvent, RenderProgress};
 use std::cmp::{max, min};
 use std::time::Instant;
 
-const LENGTH_ADJUSTMENT_FACTOR: f64 = 5.0 / 6.0; //250.0 / 295.0;
-
 #[derive(Clone)]
 pub struct Img {
     pub dimensions: Point<usize>,
@@ -46,6 +43,44 @@ impl Img {
     pub fn set_pixel(&mut self, point: Point<usize>, color: Rgba<u8>) {
         self.image.put_pixel(point.x as u32, point.y as u32, color);
     }
+    pub fn sample(&self, uv: Point<f64>) -> Rgba<u8> {
+        //! Bilinearly samples the image at uv coordinates (y going up), blending in premultiplied space
+        if self.dimensions.x == 0 || self.dimensions.y == 0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let max_x = (self.dimensions.x - 1) as f64;
+        let max_y = (self.dimensions.y - 1) as f64;
+        let x = (uv.x * self.dimensions.x as f64 - 0.5).clamp(0.0, max_x);
+        let y = ((1.0 - uv.y) * self.dimensions.y as f64 - 0.5).clamp(0.0, max_y);
+        let (fx, fy) = (x.fract(), y.fract());
+        let mut premultiplied = [0.0; 4];
+        [
+            (x.floor(), y.floor(), (1.0 - fx) * (1.0 - fy)),
+            (x.ceil(), y.floor(), fx * (1.0 - fy)),
+            (x.floor(), y.ceil(), (1.0 - fx) * fy),
+            (x.ceil(), y.ceil(), fx * fy),
+        ]
+        .into_iter()
+        .for_each(|(x, y, weight)| {
+            let pixel = self.get_pixel(Point::new(x as usize, y as usize));
+            let alpha = pixel.0[3] as f64 / 255.0 * weight;
+            premultiplied
+                .iter_mut()
+                .zip(pixel.0)
+                .take(3)
+                .for_each(|(p, c)| *p += c as f64 * alpha);
+            premultiplied[3] += alpha;
+        });
+        if premultiplied[3] <= 0.0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+        Rgba([
+            (premultiplied[0] / premultiplied[3]).round() as u8,
+            (premultiplied[1] / premultiplied[3]).round() as u8,
+            (premultiplied[2] / premultiplied[3]).round() as u8,
+            (premultiplied[3] * 255.0).round() as u8,
+        ])
+    }
 }
 
 #[derive(ErrorStack, Debug)]
@@ -55,16 +90,29 @@ pub enum SceneRenderingError {
     FrameRenderingError,
     EncodingError,
     FFMPEGError,
-    Crossterm,
+    OutputSinkError,
 }
 
+enum RenderedFrame {
+    Yuv(YUVBuffer),
+    Rgba(RgbaImage),
+}
+
+type RenderResult = (usize, Result<RenderedFrame, SceneRenderingError>);
+
 #[derive(Clone)]
 pub struct Scene {
     children: Vec<Arc<RwLock<Renderable>>>,
     resolution: Point<usize>,
-    fps: usize,
-    length: Duration,
+    fps: FrameRate,
+    frame_count: usize,
     rate_control_mode: RateControlMode,
+    ffmpeg: FFMPEGLocation,
+    progress: Option<Arc<dyn RenderProgress>>,
+    threads: usize,
+    frame_buffer: usize,
+    supersampling: usize,
+    post_processes: Vec<Box<dyn PostProcess>>,
 }
 
 impl Scene {
@@ -83,9 +131,18 @@ impl Scene {
         SceneBuilder {
             children: Some(vec![]),
             resolution: Some(Point::new(1280, 720)),
-            fps: Some(30),
+            fps: Some(FRAME_RATE_30),
             length: None,
+            frame_count: None,
             rate_control_mode: RateControlMode::Bufferbased,
+            ffmpeg: FFMPEGLocation::default(),
+            progress: None,
+            threads: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(8),
+            frame_buffer: None,
+            supersampling: 1,
+            post_processes: vec![],
         }
     }
     pub fn get_children(&self) -> &Vec<Arc<RwLock<Renderable>>> {
@@ -94,6 +151,18 @@ impl Scene {
     pub fn get_children_mut(&mut self) -> &mut Vec<Arc<RwLock<Renderable>>> {
         &mut self.children
     }
+    pub fn get_resolution(&self) -> Point<usize> {
+        self.resolution
+    }
+    pub fn get_frame_rate(&self) -> FrameRate {
+        self.fps
+    }
+    pub fn get_frame_count(&self) -> usize {
+        self.frame_count
+    }
+    pub fn get_length(&self) -> Duration {
+        self.fps.frame_time(self.frame_count)
+    }
     pub fn add_child_simple(&mut self, child: Renderable) -> Arc<RwLock<Renderable>> {
         let s = Arc::new(RwLock::new(child));
         self.children.push(s.clone());
@@ -102,371 +171,414 @@ impl Scene {
     pub fn add_child(&mut self, child: Arc<RwLock<Renderable>>) {
         self.children.push(child);
     }
-    fn render_frames(&self) -> Result<Vec<u8>, SceneRenderingError> {
-        println!("Rendering video...\n");
+    fn render_frames(&self, sink: &mut dyn OutputSink) -> Result<(), SceneRenderingError> {
         //figure out frame count, with matching duration to send to behaviour and shader
-        let max_frames = self.length.as_secs() as usize * self.fps;
-        let seconds_per_frame = 1.0 / self.fps as f64;
-        let mut video_bytes: Vec<u8> = vec![];
-        let mut encoder = Encoder::with_config(
-            EncoderConfig::new(self.resolution.x as u32, self.resolution.y as u32)
-                .max_frame_rate(self.fps as f32)
-                .rate_control_mode(self.rate_control_mode),
-        )
-        .into_report()
-        .change_context(SceneRenderingError::EncodingError)
-        .attach_printable_lazy(|| "Failed to create encoder")?;
-        let mut start = Instant::now();
-        let tp = threadpool::ThreadPool::new(8);
-        let mut receivers = vec![];
+        let max_frames = self.frame_count;
+        let format = sink.format();
+        let mut encoder = match format {
+            FrameFormat::H264 => Some(
+                Encoder::with_config(
+                    EncoderConfig::new(self.resolution.x as u32, self.resolution.y as u32)
+                        .max_frame_rate(self.fps.as_f64() as f32)
+                        .rate_control_mode(self.rate_control_mode),
+                )
+                .into_report()
+                .change_context(SceneRenderingError::EncodingError)
+                .attach_printable_lazy(|| "Failed to create encoder")?,
+            ),
+            FrameFormat::Rgba => None,
+        };
+        sink.begin(SinkInfo {
+            resolution: self.resolution,
+            frame_rate: self.fps,
+            frame_count: max_frames,
+        })
+        .change_context(SceneRenderingError::OutputSinkError)
+        .attach_printable_lazy(|| "Output sink failed to begin")?;
+        let start = Instant::now();
+        let tp = threadpool::ThreadPool::new(self.threads);
+        let mut in_flight = VecDeque::with_capacity(self.frame_buffer);
         let mut encoded_count = 0;
         let rendered_count = Arc::new(RwLock::new(0));
-        //for each frame, run render frame
-        for (frame_indx, time) in (0..max_frames)
-            .map(|i| Duration::from_secs_f64(i as f64 * seconds_per_frame))
-            .enumerate()
-        {
-            execute!(
-                std::io::stdout(),
-                BeginSynchronizedUpdate,
-                Hide,
-                MoveToPreviousLine(1),
-                SetForegroundColor(Color::Blue),
-                Print(format!("Runnning Behaviours")),
-                ResetColor,
-                Print(format!(
-                    " | Processed {} / {} frames",
-                    frame_indx, max_frames
-                )),
-                SetForegroundColor(Color::Blue),
-                Print(format!(
-                    " ({:.0}%)",
-                    (frame_indx as f64 / max_frames as f64) * 100.0
-                )),
-                ResetColor,
-                Print(" | ".to_owned()),
-                ResetColor,
-                Print("\n".to_owned()),
-                //ScrollDown(3),
-                EndSynchronizedUpdate,
-            )
-            .into_report()
-            .change_context(SceneRenderingError::Crossterm)
-            .attach_printable_lazy(|| "Failed to execute crossterm")
-            .unwrap();
+        // Waits for the oldest frame in flight, then encodes it and hands it to the sink
+        let mut write_frame = |receiver: Receiver<RenderResult>| {
+            let (frame_indx, frame) = receiver
+                .recv()
+                .into_report()
+                .change_context(SceneRenderingError::FrameRenderingError)
+                .attach_printable_lazy(|| {
+                    format!("Render thread for frame {} stopped early", encoded_count)
+                })?;
+            let frame = frame?;
+            assert_eq!(frame_indx, encoded_count);
+            match (&mut encoder, frame) {
+                (Some(encoder), RenderedFrame::Yuv(frame)) => {
+                    let bytes = encoder
+                        .encode(&frame)
+                        .into_report()
+                        .change_context(SceneRenderingError::EncodingError)
+                        .attach_printable_lazy(|| "Failed to encode frame")?
+                        .to_vec();
+                    sink.write_frame(frame_indx, Frame::H264(&bytes))
+                }
+                (_, RenderedFrame::Rgba(frame)) => {
+                    sink.write_frame(frame_indx, Frame::Rgba(&frame))
+                }
+                _ => unreachable!("Frames are only converted to YUV when encoding"),
+            }
+            .change_context(SceneRenderingError::OutputSinkError)
+            .attach_printable_lazy(|| {
+                format!("Output sink failed to write frame {}", frame_indx)
+            })?;
+            encoded_count += 1;
+            let eta = Duration::from_secs_f64(
+                start.elapsed().as_secs_f64() * ((max_frames - encoded_count) as f64)
+                    / encoded_count as f64,
+            );
+            self.report_progress(ProgressEvent::Eta(eta));
+            self.report_progress(ProgressEvent::FramesEncoded {
+                done: encoded_count,
+                total: max_frames,
+            });
+            Ok::<(), Report<SceneRenderingError>>(())
+        };
+        //for each frame, run render frame, never letting more than frame_buffer frames get ahead of the encoder
+        for (frame_indx, time) in (0..max_frames).map(|i| self.fps.frame_time(i)).enumerate() {
+            if in_flight.len() >= self.frame_buffer {
+                write_frame(in_flight.pop_front().unwrap())?;
+            }
             self.run_behaviours(time);
+            self.report_progress(ProgressEvent::FramesProcessed {
+                done: frame_indx + 1,
+                total: max_frames,
+            });
             let cloned_scene = self.clone_entire();
             let cloned_count = rendered_count.clone();
             let (sender, rec) = std::sync::mpsc::channel();
-            receivers.push(rec);
+            in_flight.push_back(rec);
             tp.execute(move || {
-                sender
-                    .send((
-                        frame_indx,
-                        rgba_to_yuv(
-                            cloned_scene
-                                .render_frame(frame_indx, time)
-                                .change_context(SceneRenderingError::FrameRenderingError)
-                                .attach_printable_lazy(|| {
-                                    format!(
-                                        "Failed to render frame {} at time {} seconds",
-                                        frame_indx,
-                                        time.as_secs_f64()
-                                    )
-                                })
-                                .unwrap(),
-                        ),
-                    ))
-                    .expect("Failed to send frame through mpsc channel");
-                *cloned_count.write().unwrap().deref_mut() += 1;
+                let frame = cloned_scene
+                    .render_frame(frame_indx, time)
+                    .change_context(SceneRenderingError::FrameRenderingError)
+                    .attach_printable_lazy(|| {
+                        format!(
+                            "Failed to render frame {} at time {} seconds",
+                            frame_indx,
+                            time.as_secs_f64()
+                        )
+                    })
+                    .map(|frame| match format {
+                        FrameFormat::H264 => RenderedFrame::Yuv(rgba_to_yuv(frame)),
+                        FrameFormat::Rgba => RenderedFrame::Rgba(frame),
+                    });
+                //The receiver is gone if an earlier frame failed and rendering stopped, so there's nobody left to send to
+                let _ = sender.send((frame_indx, frame));
+                let mut count = cloned_count.write().unwrap();
+                *count.deref_mut() += 1;
+                cloned_scene.report_progress(ProgressEvent::FramesRendered {
+                    done: *count,
+                    total: max_frames,
+                });
             });
         }
-        let mut has_finished_frames = false;
-        let mut encoded_at_start = 0;
-        for (frame_indx, receiver) in receivers.iter().enumerate() {
-            let (rendered_indx, frame) = receiver.recv().unwrap();
-            assert_eq!(frame_indx, rendered_indx);
-            encoder
-                .encode(&frame)
-                .into_report()
-                .change_context(SceneRenderingError::EncodingError)
-                .attach_printable_lazy(|| "Failed to encode frame")?
-                .write_vec(&mut video_bytes);
-            encoded_count += 1;
-            if tp.active_count() == 0 {
-                if has_finished_frames == false {
-                    has_finished_frames = true;
-                    start = Instant::now();
-                    encoded_at_start = encoded_count;
-                }
-                let eta = Duration::from_secs_f64(
-                    start.elapsed().as_secs_f64()
-                        * (((max_frames - encoded_at_start) as f64
-                            / std::cmp::max(1, encoded_count - encoded_at_start) as f64)
-                            - 1.0)
-                            .abs(),
-                );
-                execute!(
-                    std::io::stdout(),
-                    BeginSynchronizedUpdate,
-                    Hide,
-                    MoveToPreviousLine(1),
-                    SetForegroundColor(Color::Blue),
-                    Print(format!("Finished rendering frames, still encoding video")),
-                    ResetColor,
-                    Print(format!(
-                        " | Encoded {} / {} frames",
-                        encoded_count, max_frames
-                    )),
-                    SetForegroundColor(Color::Blue),
-                    Print(format!(
-                        " ({:.0}%)",
-                        (encoded_count as f64 / max_frames as f64) * 100.0
-                    )),
-                    ResetColor,
-                    Print(" | ".to_owned()),
-                    SetForegroundColor(Color::Green),
-                    Print(format!(
-                        "ETA: {}:{}",
-                        eta.as_secs() / 60,
-                        eta.as_secs() % 60
-                    )),
-                    ResetColor,
-                    Print("\n".to_owned()),
-                    //ScrollDown(3),
-                    EndSynchronizedUpdate,
-                )
-                .into_report()
-                .change_context(SceneRenderingError::Crossterm)
-                .attach_printable_lazy(|| "Failed to execute crossterm")
-                .unwrap();
-            } else if let Ok(rc) = rendered_count.read() {
-                let r_count = *rc.deref();
-                let eta = Duration::from_secs_f64(
-                    start.elapsed().as_secs_f64()
-                        * ((max_frames as f64 / std::cmp::max(1, encoded_count) as f64) - 1.0),
-                ); //minus one bcs x*a - x = x(a - 1)
-                execute!(
-                    std::io::stdout(),
-                    BeginSynchronizedUpdate,
-                    Hide,
-                    MoveToPreviousLine(1),
-                    Print(format!("Rendered frame {}/{}", r_count, max_frames)),
-                    SetForegroundColor(Color::Blue),
-                    Print(format!(
-                        " ({:.0}%)",
-                        (r_count as f64 / max_frames as f64) * 100.0
-                    )),
-                    ResetColor,
-                    Print(format!(
-                        " | Encoded {} / {} frames",
-                        encoded_count, max_frames
-                    )),
-                    SetForegroundColor(Color::Blue),
-                    Print(format!(
-                        " ({:.0}%)",
-                        (encoded_count as f64 / max_frames as f64) * 100.0
-                    )),
-                    ResetColor,
-                    Print(" | ".to_owned()),
-                    SetForegroundColor(Color::Green),
-                    Print(format!(
-                        "ETA: {}:{}",
-                        eta.as_secs() / 60,
-                        eta.as_secs() % 60
-                    )),
-                    ResetColor,
-                    Print("\n".to_owned()),
-                    EndSynchronizedUpdate,
-                )
-                .into_report()
-                .change_context(SceneRenderingError::Crossterm)
-                .attach_printable_lazy(|| "Failed to execute crossterm")
-                .unwrap();
-            }
+        while let Some(receiver) = in_flight.pop_front() {
+            write_frame(receiver)?;
         }
-        Ok(video_bytes)
+        sink.finish()
+            .change_context(SceneRenderingError::OutputSinkError)
+            .attach_printable_lazy(|| "Output sink failed to finish")?;
+        self.report_progress(ProgressEvent::Finished);
+        Ok(())
     }
-    fn run_behaviours(&self, time: Duration) {
-        let mut stack: Vec<(Point<isize>, Point<f64>, Arc<RwLock<Renderable>>)> = vec![];
-        self.children
+    fn report_progress(&self, event: ProgressEvent) {
+        if let Some(progress) = &self.progress {
+            progress.report(event);
+        }
+    }
+    pub(crate) fn run_behaviours(&self, time: Duration) {
+        //run every renderable's behaviour's process, parents before their children, in the same order they get drawn in
+        draw_order(&self.children)
             .iter()
-            .map(Clone::clone)
-            .map(|c| (Point::new(0, 0), Point::new(1.0, 1.0), c))
-            .for_each(|v| stack.push(v));
-        //for each child, run their run their behaviour's process, then for every pixel, run their get_pixel (THIS CAN EASILY BE PARRELLELIZED) and overide the pixel on the main image buffer'
-        while let Some((residual_offset, residual_scale, child)) = stack.pop() {
-            let mut child = child.write().unwrap();
-            //child.run_behaviour(time);
-
-            // This scale should be multiplied against the dimensions when calculating the bottom right point (and also get passed to children), but only residual scale should be applied to the top left point.
-            let next_scale = Point::new(
-                residual_scale.x * child.params.scale.x,
-                residual_scale.y * child.params.scale.y,
-            );
-
-            //This should be passed down to children AND used for coordinate calculations
-            let next_offset = Point::new(
-                residual_offset.x
-                    + (child.params.position.x * self.resolution.x as f64 * residual_scale.x)
-                        as isize,
-                residual_offset.y
-                    + (child.params.position.y * self.resolution.y as f64 * residual_scale.y)
-                        as isize,
+            .for_each(|child| self.run_behaviour_tree(child, Transform::IDENTITY, time));
+    }
+    fn run_behaviour_tree(
+        &self,
+        renderable: &Arc<RwLock<Renderable>>,
+        parent_transform: Transform,
+        time: Duration,
+    ) {
+        //Only hold each lock as long as needed, so behaviours can read their ancestors through the scene
+        {
+            let mut renderable = renderable.write().unwrap();
+            let transform = parent_transform * renderable.params.local_transform(self.resolution);
+            renderable.run_behaviour(
+                time,
+                self,
+                transform.origin().map_both(|v| v.round() as isize),
             );
-
-            child
-                .get_children()
-                .iter()
-                .map(Clone::clone)
-                .map(|c| (next_offset, next_scale, c))
-                .for_each(|c| stack.push(c));
-            child.run_behaviour(time, self, next_offset);
         }
+        let (transform, matte_source, children) = {
+            let renderable = renderable.read().unwrap();
+            (
+                parent_transform * renderable.params.local_transform(self.resolution),
+                renderable.params.matte.as_ref().map(|matte| matte.source.clone()),
+                draw_order(renderable.get_children()),
+            )
+        };
+        //The matte lives in the same space as the renderable it's cutting out
+        if let Some(matte_source) = matte_source {
+            self.run_behaviour_tree(&matte_source, parent_transform, time);
+        }
+        //Passing down the transform (position, rotation, skew and scale) to the children
+        children
+            .iter()
+            .for_each(|child| self.run_behaviour_tree(child, transform, time));
     }
-    fn render_frame(
+    pub(crate) fn render_frame(
         &self,
         _frame_indx: usize,
         time: Duration,
     ) -> Result<RgbaImage, SceneRenderingError> {
         //create an empty rgba image buffer
         let mut img_buffer = Img::new(self.resolution);
-        //'recursively' iterate through all children of the scene, and their children, (run from top down)
-        let mut stack: Vec<(Point<isize>, Point<f64>, Arc<RwLock<Renderable>>)> = vec![];
-
-        let offset = std::cmp::min(
-            -1,
-            (-3 as f64 * (self.resolution.x as f64 / RESOLUTION_4K.x as f64)) as isize,
+        //recursively draw all children of the scene, and their children, from the bottom up (see RenderableParams::z_index)
+        draw_order(&self.children).iter().for_each(|child| {
+            self.render_renderable(&mut img_buffer, child, Transform::IDENTITY, 1.0, time)
+        });
+        //then let the post processing stages have their way with it
+        self.post_processes
+            .iter()
+            .for_each(|stage| stage.process(&mut img_buffer, time));
+        Ok(img_buffer.image)
+    }
+    fn render_renderable(
+        &self,
+        img_buffer: &mut Img,
+        renderable: &Arc<RwLock<Renderable>>,
+        parent_transform: Transform,
+        parent_opacity: f64,
+        time: Duration,
+    ) {
+        //! Draws the renderable, then its children over it
+        let renderable = renderable.read().unwrap();
+        let opacity = parent_opacity * renderable.params.opacity.clamp(0.0, 1.0);
+        if !renderable.params.is_shown(time) || opacity <= 0.0 {
+            return;
+        }
+        let matte = match &renderable.params.matte {
+            Some(matte) => matte,
+            None => {
+                let blend_mode = renderable.params.blend_mode;
+                return self.render_subtree(
+                    img_buffer,
+                    &renderable,
+                    parent_transform,
+                    opacity,
+                    blend_mode,
+                    time,
+                );
+            }
+        };
+
+        //Render the renderable and its children offscreen, cut them out with the matte, then composite the result as one layer
+        let mut layer = Img::new(self.resolution);
+        self.render_subtree(
+            &mut layer,
+            &renderable,
+            parent_transform,
+            opacity,
+            BlendMode::Normal,
+            time,
+        );
+        let mut matte_buffer = Img::new(self.resolution);
+        self.render_renderable(
+            &mut matte_buffer,
+            &matte.source,
+            parent_transform,
+            1.0,
+            time,
         );
 
-        self.children
+        composite_matted(
+            img_buffer,
+            &layer,
+            &matte_buffer,
+            matte.mode,
+            renderable.params.blend_mode,
+        );
+    }
+    fn render_subtree(
+        &self,
+        img_buffer: &mut Img,
+        renderable: &Renderable,
+        parent_transform: Transform,
+        opacity: f64,
+        blend_mode: BlendMode,
+        time: Duration,
+    ) {
+        let transform = parent_transform * renderable.params.local_transform(self.resolution);
+        let children = draw_order(renderable.get_children());
+        let children_matte = match renderable.params.children_matte {
+            Some(children_matte) if !children.is_empty() => children_matte,
+            _ => {
+                self.render_layer(img_buffer, renderable, transform, opacity, blend_mode, time);
+                children.iter().for_each(|child| {
+                    self.render_renderable(img_buffer, child, transform, opacity, time)
+                });
+                return;
+            }
+        };
+
+        //Render its own layer offscreen at full opacity to use as the matte, and the children offscreen to be cut out by it
+        let mut matte_buffer = Img::new(self.resolution);
+        self.render_layer(
+            &mut matte_buffer,
+            renderable,
+            transform,
+            1.0,
+            BlendMode::Normal,
+            time,
+        );
+        let mut layer = Img::new(self.resolution);
+        children
             .iter()
-            .map(Clone::clone)
-            .map(|c| (Point::new(offset, offset), Point::new(1.0, 1.0), c))
-            .for_each(|v| stack.push(v));
-        //for each child, run their run their behaviour's process, then for every pixel, run their get_pixel (THIS CAN EASILY BE PARRELLELIZED) and overide the pixel on the main image buffer'
-        while let Some((residual_offset, residual_scale, child)) = stack.pop() {
-            let mut child = child.write().unwrap();
-            //child.run_behaviour(time);
-
-            // This scale should be multiplied against the dimensions when calculating the bottom right point (and also get passed to children), but only residual scale should be applied to the top left point.
-            let next_scale = Point::new(
-                residual_scale.x * child.params.scale.x,
-                residual_scale.y * child.params.scale.y,
-            );
-
-            //This should be passed down to children AND used for coordinate calculations
-            let next_offset = Point::new(
-                residual_offset.x
-                    + (child.params.position.x * self.resolution.x as f64 * residual_scale.x)
-                        as isize,
-                residual_offset.y
-                    + (child.params.position.y * self.resolution.y as f64 * residual_scale.y)
-                        as isize,
-            );
-
-            child
-                .get_children()
-                .iter()
-                .map(Clone::clone)
-                .map(|c| (next_offset, next_scale, c))
-                .for_each(|c| stack.push(c));
-
-            let abs_width = child.params.size.x * self.resolution.y as f64 * next_scale.x;
-            let abs_height = child.params.size.y * self.resolution.y as f64 * next_scale.y;
-
-            use std::f64::consts::PI;
-            let center_to_corner = 0.5 / (abs_width.powi(2) + abs_height.powi(2)).inv_sqrt64();
-            let theta_a = 2.0 * abs_height.atan2(abs_width);
-            let rot = child.params.rotation % (PI * 2.0);
-            let theta_u = if (rot >= 0.0 && rot <= PI / 2.0) || (rot >= PI && rot <= 3.0 * PI / 2.0)
-            {
-                rot
-            } else {
-                rot - theta_a
-            };
-            let d_height =
-                center_to_corner * ((theta_u + theta_a / 2.0).sin().abs() - (theta_a / 2.0).sin());
-            let theta_u = if (rot >= 0.0 && rot <= PI / 2.0) || (rot >= PI && rot <= 3.0 * PI / 2.0)
-            {
-                rot - theta_a
-            } else {
-                rot
-            };
-            let d_width =
-                center_to_corner * ((theta_u + theta_a / 2.0).cos().abs() - (theta_a / 2.0).cos());
-
-            //For every pixel within the bounds of the shader, run the get_pixel fn and overide the pixel on the main image buffer
-            let up_left_unchecked = Point::new(next_offset.x, next_offset.y);
-            let down_right_unchecked = Point::new(
-                up_left_unchecked.x + (abs_width as isize),
-                up_left_unchecked.y + (abs_height as isize),
-            );
-            let up_left = up_left_unchecked
-                .map_x(|x| x - d_width as isize)
-                .map_y(|y| y - d_height as isize)
-                .map_both(|v| max(v, 0));
-            let down_right = down_right_unchecked
-                .map_x(|x| x + d_width as isize)
-                .map_y(|y| y + d_height as isize)
-                .map_x(|x| min(self.resolution.x as isize, x))
-                .map_y(|y| min(self.resolution.y as isize, y));
-
-            let old_image = img_buffer.clone();
-
-            ((up_left.y)..(down_right.y))
-                .map(|y| {
-                    ((up_left.x)..(down_right.x))
-                        .map(|x| Point::new(x, y))
-                        .collect::<Vec<Point<isize>>>() // *may* cause bottleneck
-                })
-                .flatten()
-                .for_each(|p| {
-                    let uv = to_uv(up_left_unchecked, down_right_unchecked, p)
-                        .map_both(|v| v - 0.5)
-                        .map_x(|x| x * (abs_width as f64 / abs_height as f64))
-                        .to_polar()
-                        .map_y(|y| y - child.params.rotation)
-                        .to_cartesian()
-                        .map_x(|x| x * (abs_height as f64 / abs_width as f64))
-                        .map_both(|v| v + 0.5);
-
-                    let bounds_checked = uv.map_both(|v| (v >= 0.0 && v <= 1.0) as u8);
-
-                    let c = Point::new(p.x as usize, p.y as usize);
-
-                    if bounds_checked.x == 0 || bounds_checked.y == 0 {
-                        img_buffer.set_pixel(c, Rgba([0, 0, 0, 0]));
-                        return ();
-                    }
-
-                    let color = child.run_shader(&old_image, uv, time, next_offset);
-                    let current_color = old_image.get_pixel(c);
-                    let a = color.0[3];
-                    let mixer = |i: usize| {
-                        (color.0[i] as f32 / 255.0 * a as f32
-                            + current_color.0[i] as f32 / 255.0 * (255.0 - a as f32))
-                            as u8
-                    };
-                    let a_mixer = || {
-                        a.checked_add(current_color.0[3].checked_mul(255 - a).unwrap_or(255))
-                            .unwrap_or(255)
-                    }; //Not sure if this is how alpha mixing SHOULD work, but it seems right?
-                    let new_color = Rgba([mixer(0), mixer(1), mixer(2), a_mixer()]);
-
-                    img_buffer.set_pixel(c, new_color);
-                })
+            .for_each(|child| self.render_renderable(&mut layer, child, transform, opacity, time));
+        if children_matte.show_self {
+            self.render_layer(img_buffer, renderable, transform, opacity, blend_mode, time);
         }
-        //write image buffer to file
-        Ok(img_buffer.image)
+        composite_matted(
+            img_buffer,
+            &layer,
+            &matte_buffer,
+            children_matte.mode,
+            BlendMode::Normal,
+        );
     }
-    /*
-    fn post_process_frame() -> Result<(), SceneRenderingError> {
-        In future, potentially add an optional post-processing feild to scene, which takes an takes the final image buffer and is free to operate on it any way it wants.
-        That would be run in this fn, which would be run directly after the 'render_frame()' fn.
+    fn render_layer(
+        &self,
+        img_buffer: &mut Img,
+        renderable: &Renderable,
+        transform: Transform,
+        opacity: f64,
+        blend_mode: BlendMode,
+        time: Duration,
+    ) {
+        //! Runs the renderable's get_pixel for every pixel it covers (in parallel across rows) and composites it onto the image buffer
+        let abs_position = transform.origin().map_both(|v| v.round() as isize);
+        let size = renderable.params.pixel_size(self.resolution);
+        let inverse = match transform.inverse() {
+            Some(inverse) => inverse,
+            None => return, //Squashed flat, so there's nothing to draw
+        };
+
+        //Screen space bounds of the transformed renderable, padded by a pixel so the anti-aliased edges aren't cut off
+        let corners = [(0.0, 0.0), (size.x, 0.0), (0.0, size.y), (size.x, size.y)]
+            .map(|(x, y)| transform.apply(Point::new(x, y)));
+        let up_left = Point::new(
+            corners.iter().map(|c| c.x).fold(f64::INFINITY, f64::min),
+            corners.iter().map(|c| c.y).fold(f64::INFINITY, f64::min),
+        )
+        .map_both(|v| max(v.floor() as isize - 1, 0));
+        let down_right = Point::new(
+            corners
+                .iter()
+                .map(|c| c.x)
+                .fold(f64::NEG_INFINITY, f64::max),
+            corners
+                .iter()
+                .map(|c| c.y)
+                .fold(f64::NEG_INFINITY, f64::max),
+        )
+        .map_both(|v| v.ceil() as isize + 1)
+        .map_x(|x| min(self.resolution.x as isize, x))
+        .map_y(|y| min(self.resolution.y as isize, y));
+
+        //Screen pixels per local pixel, measured across the edges parallel to each axis
+        let edge_scale = Point::new(
+            transform.determinant().abs() / transform.y_axis().x.hypot(transform.y_axis().y),
+            transform.determinant().abs() / transform.x_axis().x.hypot(transform.x_axis().y),
+        );
+
+        let samples = renderable
+            .params
+            .supersampling
+            .unwrap_or(self.supersampling)
+            .max(1);
+        let to_local_uv = |p: Point<f64>| {
+            let local = inverse.apply(p);
+            Point::new(local.x / size.x, 1.0 - local.y / size.y) //flip y, uv has y going up
+        };
+
+        //Shade the rows in parallel against the frame as it was before this layer, then write them back
+        let layer = ((up_left.y)..(down_right.y))
+            .into_par_iter()
+            .map(|y| {
+                ((up_left.x)..(down_right.x))
+                    .map(|x| {
+                        let current_color =
+                            img_buffer.get_pixel(Point::new(x as usize, y as usize));
+
+                        //Average a grid of samples x samples sub-pixels, in premultiplied space so transparent samples don't darken the edges
+                        let mut premultiplied = [0.0; 4];
+                        for sub_y in 0..samples {
+                            for sub_x in 0..samples {
+                                let uv = to_local_uv(Point::new(
+                                    x as f64 + (sub_x as f64 + 0.5) / samples as f64,
+                                    y as f64 + (sub_y as f64 + 0.5) / samples as f64,
+                                ));
+
+                                //Distance in sub-pixels from the sample to the nearest edge, negative when outside the renderable
+                                let edge_distance = (uv.x.min(1.0 - uv.x) * size.x * edge_scale.x)
+                                    .min(uv.y.min(1.0 - uv.y) * size.y * edge_scale.y)
+                                    * samples as f64;
+                                let coverage = (edge_distance + 0.5).clamp(0.0, 1.0);
+                                if coverage <= 0.0 {
+                                    continue;
+                                }
+
+                                let color = renderable.run_shader(
+                                    img_buffer,
+                                    uv.map_both(|v| v.clamp(0.0, 1.0)),
+                                    time,
+                                    abs_position,
+                                );
+                                let alpha = color.0[3] as f64 / 255.0 * coverage;
+                                premultiplied
+                                    .iter_mut()
+                                    .zip(color.0)
+                                    .take(3)
+                                    .for_each(|(p, c)| *p += c as f64 * alpha);
+                                premultiplied[3] += alpha;
+                            }
+                        }
+                        let alpha = premultiplied[3] / (samples * samples) as f64 * opacity;
+                        if alpha <= 0.0 {
+                            return current_color;
+                        }
+                        let color = Rgba([
+                            (premultiplied[0] / premultiplied[3]).round() as u8,
+                            (premultiplied[1] / premultiplied[3]).round() as u8,
+                            (premultiplied[2] / premultiplied[3]).round() as u8,
+                            (alpha * 255.0).round() as u8,
+                        ]);
+                        blend_mode.composite(current_color, color)
+                    })
+                    .collect::<Vec<Rgba<u8>>>()
+            })
+            .collect::<Vec<Vec<Rgba<u8>>>>();
+
+        layer
+            .into_iter()
+            .zip(up_left.y..down_right.y)
+            .for_each(|(row, y)| {
+                row.into_iter()
+                    .zip(up_left.x..down_right.x)
+                    .for_each(|(color, x)| {
+                        img_buffer.set_pixel(Point::new(x as usize, y as usize), color)
+                    })
+            });
     }
-     */
-    fn compile_video(&self, bytes: Vec<u8>) -> Result<PathBuf, SceneRenderingError> {
+    fn unused_output_path(&self) -> Result<PathBuf, SceneRenderingError> {
         //Create Output dir if it doesn't exist
         if !Path::new("./output").exists() {
             DirBuilder::new()
@@ -477,112 +589,91 @@ impl Scene {
                 .attach_printable_lazy(|| "Failed to create output directory")?;
         }
         //Generate an unused filename
-        let mut output_filename = "./output/scene_0.mp4".to_owned();
-        let mut i = 1;
-        while Path::new(&output_filename).exists() {
-            output_filename = format!("./output/scene_{}.mp4", i);
+        let mut i = 0;
+        while Path::new(&format!("./output/scene_{}.mp4", i)).exists() {
             i += 1;
         }
-        let mut output_filename = std::path::PathBuf::from(output_filename);
-        output_filename.set_extension("h264");
-        //Use this command (add formatting)
-        //self.run_ffmpeg_cmd(output_filename)
-        let _file = std::fs::File::create(&output_filename);
-        std::fs::write(&output_filename, bytes)
-            .into_report()
+        Ok(PathBuf::from(format!("./output/scene_{}.mp4", i)))
+    }
+    pub fn render(&self) -> Result<PathBuf, SceneRenderingError> {
+        //! Renders the scene to an unused `./output/scene_N.mp4` with the built-in muxer, and returns the path written to.
+        let path = self.unused_output_path()?;
+        self.render_mp4(&path)?;
+        Ok(path)
+    }
+    pub fn render_to(&self, sink: &mut dyn OutputSink) -> Result<(), SceneRenderingError> {
+        //! Renders the scene, handing every frame to `sink` in order.
+        self.render_frames(sink)
+            .attach_printable_lazy(|| "Failed to render frames")
+    }
+    pub fn render_mp4<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneRenderingError> {
+        //! Renders the scene to an mp4 file at `path` using the built-in muxer, so no ffmpeg is required.
+        let mut sink = Mp4Sink::create(path)
             .change_context(SceneRenderingError::FileWritingError)
-            .attach_printable_lazy(|| "Failed to write video file")?;
-        Ok(PathBuf::from(output_filename))
-    }
-    fn run_ffmpeg_cmd(&self, path: PathBuf) -> Result<(), SceneRenderingError> {
-        let glob_path = std::env::current_dir()
-            .into_report()
-            .change_context(SceneRenderingError::FFMPEGError)
-            .attach_printable_lazy(|| "Failed to get current directory")?
-            .as_os_str()
-            .to_str()
-            .ok_or(Report::new(SceneRenderingError::FFMPEGError))
-            .attach_printable_lazy(|| "Failed to convert current directory to string")?
-            .to_owned();
-
-        let mut temp_mp4_path = path.clone().display().to_string();
-        temp_mp4_path = temp_mp4_path.replace(".h264", "_temp.mp4");
-        let mut mp4_path = path.clone();
-        mp4_path.set_extension("mp4");
-
-        let mut cmd = Command::new("ffmpeg")
-            .current_dir(glob_path.clone())
-            .arg("-i")
-            .arg(format!("{}", path.display()))
-            .arg(format!("{}", temp_mp4_path))
-            .spawn()
-            .into_report()
-            .change_context(SceneRenderingError::FFMPEGError)
-            .attach_printable_lazy(|| "Failed to convert video file")?;
-        cmd.wait().unwrap();
-
-        let mut cmd = Command::new("ffmpeg")
-            .current_dir(glob_path)
-            .arg("-i")
-            .arg(temp_mp4_path.clone())
-            .arg("-vf")
-            .arg(format!(
-                "setpts={}*PTS",
-                (30.0 / self.fps as f64) * LENGTH_ADJUSTMENT_FACTOR
-            ))
-            .arg("-r")
-            .arg(format!("{}", self.fps))
-            .arg(mp4_path.display().to_string())
-            .spawn()
-            .into_report()
-            .change_context(SceneRenderingError::FFMPEGError)
-            .attach_printable_lazy(|| "Failed to adjust speed of video file")?;
-        cmd.wait().unwrap();
-
-        std::fs::remove_file(temp_mp4_path).unwrap();
-        std::fs::remove_file(path).unwrap();
-
-        //ffmpeg -reinit_filter 0 -f concat -safe 0 -i "frames/dict.txt" -vf "scale=1280:720:force_original_aspect_ratio=decrease:eval=frame,pad=1280:720:-1:-1:color=black:eval=frame,settb=AVTB,setpts=0.033333333*N/TB,format=yuv420p" -r 30 -movflags +faststart output.mp4
-
-        //ffmpeg -f concat -safe 0 -i "frames/dict.txt" -vf "setpts=0.033333333*N/TB" -r 30 -movflags +faststart output.mp4
-        /*let glob_path = std::env::current_dir()
-            .into_report()
-            .change_context(SceneRenderingError::FFMPEGError)
-            .attach_printable_lazy(|| "Failed to get current directory")?
-            .as_os_str()
-            .to_str()
+            .attach_printable_lazy(|| "Failed to create video file")?;
+        self.render_to(&mut sink)
+    }
+    pub fn render_alpha<P: AsRef<Path>>(
+        &self,
+        path: P,
+        profile: AlphaProfile,
+    ) -> Result<(), SceneRenderingError> {
+        //! Renders the scene with its alpha channel intact, encoding the RGBA frames with ffmpeg using `profile`.
+        //!
+        //! `path` should have the extension given by `profile.extension()`.
+        let ffmpeg = self
+            .ffmpeg
+            .find()?
             .ok_or(Report::new(SceneRenderingError::FFMPEGError))
-            .attach_printable_lazy(|| "Failed to convert current directory to string")?
-            .to_owned();
-
-        Command::new("ffmpeg")
-            .current_dir(glob_path)
-            .arg("-f")
-            .arg("concat")
-            .arg("-safe")
-            .arg("0")
-            .arg("-i")
-            .arg("./frames/dict.txt")
-            .arg("-filter")
-            .arg(format!("setpts={h}*N/TB", h = 1.0 / self.fps as f32))
-            .arg("-r")
-            .arg(format!("{}", self.fps))
-            .arg("-movflags")
-            .arg("+faststart")
-            .arg(path)
-            .spawn()
-            .into_report()
-            .change_context(SceneRenderingError::FFMPEGError)
-            .attach_printable_lazy(|| "Failed to concatinate frames into video through ffmpeg")?;
-        */
-        Ok(())
+            .attach_printable("Rendering with alpha needs ffmpeg, but it is disabled")?;
+        self.render_to(&mut FFMPEGSink::new(ffmpeg, path, profile))
+    }
+    pub fn render_gif<P: AsRef<Path>>(
+        &self,
+        path: P,
+        dithering: bool,
+    ) -> Result<(), SceneRenderingError> {
+        //! Renders the scene to a looping animated GIF at `path`.
+        let sink = GifSink::create(path)
+            .change_context(SceneRenderingError::FileWritingError)
+            .attach_printable_lazy(|| "Failed to create gif file")?;
+        self.render_to(&mut sink.with_dithering(dithering))
+    }
+    pub fn render_apng<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneRenderingError> {
+        //! Renders the scene to a looping, lossless animated PNG at `path`.
+        let mut sink = ApngSink::create(path)
+            .change_context(SceneRenderingError::FileWritingError)
+            .attach_printable_lazy(|| "Failed to create apng file")?;
+        self.render_to(&mut sink)
     }
-    pub fn render(&self) -> Result<(), SceneRenderingError> {
-        ffmpeg_sidecar::download::auto_download().unwrap();
-        let bytes = self
-            .render_frames()
-            .attach_printable_lazy(|| "Failed to render frames")?;
-        self.run_ffmpeg_cmd(self.compile_video(bytes)?)
+    pub fn render_still(&self, time: Duration) -> Result<Img, SceneRenderingError> {
+        //! Renders the single frame shown at `time`.
+        //!
+        //! Works on a copy of the scene, running the behaviours of every frame leading up to `time` first, so stateful behaviours end up exactly where they would be in a full render.
+        let scene = self.clone_entire();
+        let frame_indx = self.fps.frame_at(time);
+        (0..frame_indx)
+            .map(|i| self.fps.frame_time(i))
+            .for_each(|t| scene.run_behaviours(t));
+        scene.run_behaviours(time);
+        let image = scene
+            .render_frame(frame_indx, time)
+            .change_context(SceneRenderingError::FrameRenderingError)
+            .attach_printable_lazy(|| {
+                format!("Failed to render still at {} seconds", time.as_secs_f64())
+            })?;
+        Ok(Img {
+            dimensions: self.resolution,
+            image,
+        })
+    }
+    pub fn render_image_sequence<P: AsRef<Path>>(
+        &self,
+        directory: P,
+        format: ImageFormat,
+    ) -> Result<(), SceneRenderingError> {
+        //! Renders every frame as a numbered image in `directory`, skipping video encoding entirely.
+        self.render_to(&mut ImageSequenceSink::new(directory).with_format(format))
     }
 }
 
@@ -593,9 +684,16 @@ pub struct SceneBuilderError;
 pub struct SceneBuilder {
     children: Option<Vec<Arc<RwLock<Renderable>>>>,
     resolution: Option<Point<usize>>,
-    fps: Option<usize>,
+    fps: Option<FrameRate>,
     length: Option<Duration>,
+    frame_count: Option<usize>,
     rate_control_mode: RateControlMode,
+    ffmpeg: FFMPEGLocation,
+    progress: Option<Arc<dyn RenderProgress>>,
+    threads: usize,
+    frame_buffer: Option<usize>,
+    supersampling: usize,
+    post_processes: Vec<Box<dyn PostProcess>>,
 }
 
 impl SceneBuilder {
@@ -604,11 +702,53 @@ impl SceneBuilder {
         self
     }
     pub fn with_fps(&mut self, fps: usize) -> &mut Self {
-        self.fps = Some(fps);
+        self.fps = Some(fps.into());
+        self
+    }
+    pub fn with_frame_rate(&mut self, frame_rate: FrameRate) -> &mut Self {
+        //! For rates that aren't whole numbers, like `FRAME_RATE_29_97`.
+        self.fps = Some(frame_rate);
         self
     }
     pub fn with_length(&mut self, length: Duration) -> &mut Self {
-        self.length = Some(Duration::from_secs_f64(length.as_secs_f64()));
+        //! The scene gets as many frames as it takes to cover `length`, overrides `with_frame_count`.
+        self.length = Some(length);
+        self.frame_count = None;
+        self
+    }
+    pub fn with_frame_count(&mut self, frame_count: usize) -> &mut Self {
+        //! Sets the exact number of frames to render, overrides `with_length`.
+        self.frame_count = Some(frame_count);
+        self.length = None;
+        self
+    }
+    pub fn with_ffmpeg(&mut self, ffmpeg: FFMPEGLocation) -> &mut Self {
+        self.ffmpeg = ffmpeg;
+        self
+    }
+    pub fn with_progress(&mut self, progress: Box<dyn RenderProgress>) -> &mut Self {
+        //! Gets told how rendering is going, nothing is reported by default.
+        self.progress = Some(Arc::from(progress));
+        self
+    }
+    pub fn with_threads(&mut self, threads: usize) -> &mut Self {
+        //! How many frames get rendered in parallel, defaults to the number of cpus.
+        self.threads = threads.max(1);
+        self
+    }
+    pub fn with_frame_buffer(&mut self, frame_buffer: usize) -> &mut Self {
+        //! The most frames that can be cloned and rendered ahead of the encoder at once, which bounds memory use. Defaults to twice the thread count.
+        self.frame_buffer = Some(frame_buffer.max(1));
+        self
+    }
+    pub fn with_supersampling(&mut self, samples: usize) -> &mut Self {
+        //! Each pixel is shaded from a samples x samples grid of sub-pixels and averaged, which anti-aliases shader edges. Defaults to 1 (no supersampling), and can be overidden per renderable.
+        self.supersampling = samples.max(1);
+        self
+    }
+    pub fn with_post_process(&mut self, stage: Box<dyn PostProcess>) -> &mut Self {
+        //! Adds a stage that modifies every finished frame, stages run in the order they're added
+        self.post_processes.push(stage);
         self
     }
     pub fn add_child(&mut self, child: Renderable) -> &mut Self {
@@ -636,9 +776,18 @@ impl SceneBuilder {
             err = true;
             report = report.attach_printable("No fps was set");
         }
-        if self.length.is_none() {
+        if let Some(fps) = self.fps {
+            if fps.numerator == 0 || fps.denominator == 0 {
+                err = true;
+                report = report.attach_printable(format!(
+                    "Frame rate {} is invalid, both parts must be above zero",
+                    fps
+                ));
+            }
+        }
+        if self.length.is_none() && self.frame_count.is_none() {
             err = true;
-            report = report.attach_printable("No length was set");
+            report = report.attach_printable("No length or frame count was set");
         }
         if err {
             return report;
@@ -648,8 +797,16 @@ impl SceneBuilder {
             children: std::mem::replace(&mut self.children, None).unwrap(),
             resolution: self.resolution.unwrap(),
             fps: self.fps.unwrap(),
-            length: std::mem::replace(&mut self.length, None).unwrap(),
+            frame_count: self
+                .frame_count
+                .unwrap_or_else(|| self.fps.unwrap().frames_in(self.length.unwrap())),
             rate_control_mode: self.rate_control_mode,
+            ffmpeg: self.ffmpeg.clone(),
+            progress: self.progress.clone(),
+            threads: self.threads,
+            frame_buffer: self.frame_buffer.unwrap_or(self.threads * 2),
+            supersampling: self.supersampling,
+            post_processes: self.post_processes.clone(),
         })
     }
 }
@@ -664,3 +821,42 @@ pub fn to_uv(
         1.0 - (point.y - top_left.y) as f64 / (bottom_right.y - top_left.y) as f64, //flip y (NOTE: This ASSUMES Image uses crt-style coordinates, may be unnecessary it it doesn't)
     )
 }
+
+fn composite_matted(
+    img_buffer: &mut Img,
+    layer: &Img,
+    matte_buffer: &Img,
+    mode: MatteMode,
+    blend_mode: BlendMode,
+) {
+    //! Composites layer onto img_buffer, cut out by what was rendered into matte_buffer
+    img_buffer
+        .image
+        .par_chunks_mut(4)
+        .zip(layer.image.par_chunks(4))
+        .zip(matte_buffer.image.par_chunks(4))
+        .for_each(|((backdrop, source), matte_pixel)| {
+            let amount = mode.amount(Rgba([
+                matte_pixel[0],
+                matte_pixel[1],
+                matte_pixel[2],
+                matte_pixel[3],
+            ]));
+            let alpha = (source[3] as f64 * amount).round() as u8;
+            if alpha == 0 {
+                return;
+            }
+            let color = blend_mode.composite(
+                Rgba([backdrop[0], backdrop[1], backdrop[2], backdrop[3]]),
+                Rgba([source[0], source[1], source[2], alpha]),
+            );
+            backdrop.copy_from_slice(&color.0);
+        });
+}
+
+fn draw_order(renderables: &[Arc<RwLock<Renderable>>]) -> Vec<Arc<RwLock<Renderable>>> {
+    //! Sorted by z_index, renderables with the same z_index keep the order they were added in
+    let mut sorted = renderables.to_vec();
+    sorted.sort_by_cached_key(|r| r.read().unwrap().params.z_index);
+    sorted
+}
@@ -1,10 +1,16 @@
-use super::Curve;
+use super::easing::Easing;
+use super::{
+    cubic_bezier, merge_duplicate_keys, segment_end, solve_cubic_bezier, sort_keys, Curve,
+};
 
 use num_traits::Float;
 use std::fmt::Display;
 
 pub trait ChainableCurve: Curve {
     type Value: Float;
+    /// Takes (time, value) keys in any order, keys at non-finite times are dropped. Keys sharing a time jump straight from the first to the last,
+    /// except on the spline and bezier curves, which keep only the last as they need segments with some length.
+    /// Curves built straight from their fields must already be sorted by time, as lookups binary search.
     fn new(
         data: Vec<(
             <Self as ChainableCurve>::Value,
@@ -19,7 +25,7 @@ pub struct LinearPiecewiseCurve<T: Float + Clone + Send + Sync + Display>(pub Ve
 impl<T: Float + Clone + Send + Sync + Display> Curve for LinearPiecewiseCurve<T> {
     type Value = T;
     fn get_value(&self, t: Self::Value) -> Self::Value {
-        if self.0.is_empty() {
+        if self.0.is_empty() || t.is_nan() {
             return T::nan();
         }
         if self.0.len() == 1 || t <= self.0[0].0 {
@@ -29,15 +35,8 @@ impl<T: Float + Clone + Send + Sync + Display> Curve for LinearPiecewiseCurve<T>
             return self.0.last().unwrap().1;
         }
 
-        let mut bound_indexs = (0, 0);
-        for (i, p) in self.0.iter().enumerate() {
-            bound_indexs.1 = i;
-            if p.0 > t {
-                break;
-            }
-            bound_indexs.0 = i;
-            continue;
-        }
+        let end = segment_end(&self.0, |p| p.0, t);
+        let bound_indexs = (end - 1, end);
         let slope = (self.0[bound_indexs.1].1 - self.0[bound_indexs.0].1)
             / (self.0[bound_indexs.1].0 - self.0[bound_indexs.0].0);
         let intercept = self.0[bound_indexs.0].1 - slope * self.0[bound_indexs.0].0;
@@ -53,6 +52,8 @@ impl<T: Float + Clone + Send + Sync + Display> ChainableCurve for LinearPiecewis
             <Self as ChainableCurve>::Value,
         )>,
     ) -> Self {
+        let mut data = data;
+        sort_keys(&mut data, |p| p.0);
         LinearPiecewiseCurve(data)
     }
 }
@@ -66,7 +67,7 @@ where
 {
     type Value = T;
     fn get_value(&self, t: Self::Value) -> Self::Value {
-        if self.0.is_empty() {
+        if self.0.is_empty() || t.is_nan() {
             return T::nan();
         }
         if self.0.len() == 1 || t <= self.0[0].0 {
@@ -76,15 +77,8 @@ where
             return self.0.last().unwrap().1;
         }
 
-        let mut bound_indexs = (0, 0);
-        for (i, p) in self.0.iter().enumerate() {
-            bound_indexs.1 = i;
-            if p.0 > t {
-                break;
-            }
-            bound_indexs.0 = i;
-            continue;
-        }
+        let end = segment_end(&self.0, |p| p.0, t);
+        let bound_indexs = (end - 1, end);
 
         let t_to_f64 = |t: T| -> f64 { Into::<f64>::into(t) };
 
@@ -118,6 +112,356 @@ where
             <Self as ChainableCurve>::Value,
         )>,
     ) -> Self {
+        let mut data = data;
+        sort_keys(&mut data, |p| p.0);
         SmoothCurve(data)
     }
 }
+
+/// A key on a CubicBezierCurve. The handles are offsets (time, value) from the key, and are clamped so they can't reach past the neighbouring keys in time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BezierKey<T: Float + Clone + Send + Sync + Display> {
+    pub time: T,
+    pub value: T,
+    pub in_handle: (T, T),
+    pub out_handle: (T, T),
+}
+
+impl<T: Float + Clone + Send + Sync + Display> BezierKey<T> {
+    pub fn new(time: T, value: T, in_handle: (T, T), out_handle: (T, T)) -> Self {
+        Self {
+            time,
+            value,
+            in_handle,
+            out_handle,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CubicBezierCurve<T: Float + Clone + Send + Sync + Display>(pub Vec<BezierKey<T>>);
+
+impl<T: Float + Clone + Send + Sync + Display> Curve for CubicBezierCurve<T> {
+    type Value = T;
+    fn get_value(&self, t: Self::Value) -> Self::Value {
+        if self.0.is_empty() || t.is_nan() {
+            return T::nan();
+        }
+        if self.0.len() == 1 || t <= self.0[0].time {
+            return self.0[0].value;
+        }
+        if t >= self.0.last().unwrap().time {
+            return self.0.last().unwrap().value;
+        }
+
+        let end = segment_end(&self.0, |k| k.time, t);
+        let (p1, p2) = (self.0[end - 1], self.0[end]);
+
+        let to_f64 = |v: T| v.to_f64().unwrap();
+        let dt = to_f64(p2.time - p1.time);
+        let s = solve_cubic_bezier(
+            to_f64(p1.out_handle.0) / dt,
+            1.0 + to_f64(p2.in_handle.0) / dt,
+            to_f64(t - p1.time) / dt,
+        );
+        T::from(cubic_bezier(
+            to_f64(p1.value),
+            to_f64(p1.value + p1.out_handle.1),
+            to_f64(p2.value + p2.in_handle.1),
+            to_f64(p2.value),
+            s,
+        ))
+        .unwrap()
+    }
+}
+
+impl<T: Float + Clone + Send + Sync + Display> ChainableCurve for CubicBezierCurve<T> {
+    type Value = T;
+    fn new(
+        data: Vec<(
+            <Self as ChainableCurve>::Value,
+            <Self as ChainableCurve>::Value,
+        )>,
+    ) -> Self {
+        //! Flat handles reaching a third of the way to the neighbouring keys, like After Effects' easy ease
+        let mut data = data;
+        sort_keys(&mut data, |p| p.0);
+        merge_duplicate_keys(&mut data, |p| p.0);
+        let third = T::from(1.0 / 3.0).unwrap();
+        let keys = (0..data.len())
+            .map(|i| {
+                let (time, value) = data[i];
+                let in_handle = match i.checked_sub(1) {
+                    Some(prev) => ((data[prev].0 - time) * third, T::zero()),
+                    None => (T::zero(), T::zero()),
+                };
+                let out_handle = match data.get(i + 1) {
+                    Some(next) => ((next.0 - time) * third, T::zero()),
+                    None => (T::zero(), T::zero()),
+                };
+                BezierKey::new(time, value, in_handle, out_handle)
+            })
+            .collect();
+        CubicBezierCurve(keys)
+    }
+}
+
+/// Eases every segment between points with the same Easing
+#[derive(Clone)]
+pub struct EasingCurve<T: Float + Clone + Send + Sync + Display> {
+    pub points: Vec<(T, T)>,
+    pub easing: Easing,
+}
+
+impl<T: Float + Clone + Send + Sync + Display> EasingCurve<T> {
+    pub fn with_easing(points: Vec<(T, T)>, easing: Easing) -> Self {
+        //! Sorts the points like ChainableCurve::new
+        let mut points = points;
+        sort_keys(&mut points, |p| p.0);
+        Self { points, easing }
+    }
+}
+
+impl<T: Float + Clone + Send + Sync + Display> Curve for EasingCurve<T> {
+    type Value = T;
+    fn get_value(&self, t: Self::Value) -> Self::Value {
+        if self.points.is_empty() || t.is_nan() {
+            return T::nan();
+        }
+        if self.points.len() == 1 || t <= self.points[0].0 {
+            return self.points[0].1;
+        }
+        if t >= self.points.last().unwrap().0 {
+            return self.points.last().unwrap().1;
+        }
+
+        let end = segment_end(&self.points, |p| p.0, t);
+        let (p1, p2) = (self.points[end - 1], self.points[end]);
+        let progress = ((t - p1.0) / (p2.0 - p1.0)).to_f64().unwrap();
+        p1.1 + (p2.1 - p1.1) * T::from(self.easing.ease(progress)).unwrap()
+    }
+}
+
+impl<T: Float + Clone + Send + Sync + Display> ChainableCurve for EasingCurve<T> {
+    type Value = T;
+    fn new(
+        data: Vec<(
+            <Self as ChainableCurve>::Value,
+            <Self as ChainableCurve>::Value,
+        )>,
+    ) -> Self {
+        //! Uses Easing::EASE, the same default as CSS transitions
+        Self::with_easing(data, Easing::EASE)
+    }
+}
+
+/// Cubic Hermite spline through (time, value, slope) keys, slope being the change in value per unit of time.
+/// CatmullRomCurve, MonotoneCubicCurve and NaturalCubicCurve are all built on this, they just pick the slopes differently.
+#[derive(Clone)]
+pub struct HermiteCurve<T: Float + Clone + Send + Sync + Display>(pub Vec<(T, T, T)>);
+
+impl<T: Float + Clone + Send + Sync + Display> HermiteCurve<T> {
+    fn from_slopes(data: &[(T, T)], slopes: Vec<f64>) -> Self {
+        HermiteCurve(
+            data.iter()
+                .zip(slopes)
+                .map(|(&(t, v), slope)| (t, v, T::from(slope).unwrap()))
+                .collect(),
+        )
+    }
+}
+
+impl<T: Float + Clone + Send + Sync + Display> Curve for HermiteCurve<T> {
+    type Value = T;
+    fn get_value(&self, t: Self::Value) -> Self::Value {
+        if self.0.is_empty() || t.is_nan() {
+            return T::nan();
+        }
+        if self.0.len() == 1 || t <= self.0[0].0 {
+            return self.0[0].1;
+        }
+        if t >= self.0.last().unwrap().0 {
+            return self.0.last().unwrap().1;
+        }
+
+        let end = segment_end(&self.0, |p| p.0, t);
+        let to_f64 = |v: T| v.to_f64().unwrap();
+        let (t1, v1, m1) = self.0[end - 1];
+        let (t2, v2, m2) = self.0[end];
+        let h = to_f64(t2 - t1);
+        let s = to_f64(t - t1) / h;
+        let value = (2.0 * s.powi(3) - 3.0 * s.powi(2) + 1.0) * to_f64(v1)
+            + (s.powi(3) - 2.0 * s.powi(2) + s) * h * to_f64(m1)
+            + (-2.0 * s.powi(3) + 3.0 * s.powi(2)) * to_f64(v2)
+            + (s.powi(3) - s.powi(2)) * h * to_f64(m2);
+        T::from(value).unwrap()
+    }
+}
+
+fn points_to_f64<T: Float>(data: &[(T, T)]) -> Vec<(f64, f64)> {
+    data.iter()
+        .map(|(t, v)| (t.to_f64().unwrap(), v.to_f64().unwrap()))
+        .collect()
+}
+
+fn secant_slopes(points: &[(f64, f64)]) -> Vec<f64> {
+    //! The slope of the straight line across each segment
+    points
+        .windows(2)
+        .map(|w| (w[1].1 - w[0].1) / (w[1].0 - w[0].0))
+        .collect()
+}
+
+/// Passes through every key with continuous velocity, the slope at each key points from the previous key to the next one.
+/// Can overshoot between keys.
+#[derive(Clone)]
+pub struct CatmullRomCurve<T: Float + Clone + Send + Sync + Display>(pub HermiteCurve<T>);
+
+impl<T: Float + Clone + Send + Sync + Display> Curve for CatmullRomCurve<T> {
+    type Value = T;
+    fn get_value(&self, t: Self::Value) -> Self::Value {
+        self.0.get_value(t)
+    }
+}
+
+impl<T: Float + Clone + Send + Sync + Display> ChainableCurve for CatmullRomCurve<T> {
+    type Value = T;
+    fn new(
+        data: Vec<(
+            <Self as ChainableCurve>::Value,
+            <Self as ChainableCurve>::Value,
+        )>,
+    ) -> Self {
+        let mut data = data;
+        sort_keys(&mut data, |p| p.0);
+        merge_duplicate_keys(&mut data, |p| p.0);
+        let points = points_to_f64(&data);
+        let secants = secant_slopes(&points);
+        let slopes = (0..points.len())
+            .map(|i| match (i.checked_sub(1), points.get(i + 1)) {
+                (Some(prev), Some(next)) => (next.1 - points[prev].1) / (next.0 - points[prev].0),
+                //The end keys just carry on the slope of their segment
+                (None, Some(_)) => secants[0],
+                (Some(_), None) => secants[i - 1],
+                (None, None) => 0.0,
+            })
+            .collect();
+        CatmullRomCurve(HermiteCurve::from_slopes(&data, slopes))
+    }
+}
+
+/// Like CatmullRomCurve, but never overshoots: it only ever moves between the values of the keys on either side (Fritsch-Carlson).
+#[derive(Clone)]
+pub struct MonotoneCubicCurve<T: Float + Clone + Send + Sync + Display>(pub HermiteCurve<T>);
+
+impl<T: Float + Clone + Send + Sync + Display> Curve for MonotoneCubicCurve<T> {
+    type Value = T;
+    fn get_value(&self, t: Self::Value) -> Self::Value {
+        self.0.get_value(t)
+    }
+}
+
+impl<T: Float + Clone + Send + Sync + Display> ChainableCurve for MonotoneCubicCurve<T> {
+    type Value = T;
+    fn new(
+        data: Vec<(
+            <Self as ChainableCurve>::Value,
+            <Self as ChainableCurve>::Value,
+        )>,
+    ) -> Self {
+        let mut data = data;
+        sort_keys(&mut data, |p| p.0);
+        merge_duplicate_keys(&mut data, |p| p.0);
+        let points = points_to_f64(&data);
+        let secants = secant_slopes(&points);
+        if secants.is_empty() {
+            return MonotoneCubicCurve(HermiteCurve::from_slopes(&data, vec![0.0; data.len()]));
+        }
+        let mut slopes = (0..points.len())
+            .map(|i| match (i.checked_sub(1), secants.get(i)) {
+                //Flat at local peaks and troughs
+                (Some(prev), Some(&next)) if secants[prev] * next <= 0.0 => 0.0,
+                (Some(prev), Some(&next)) => (secants[prev] + next) / 2.0,
+                (None, Some(&next)) => next,
+                (Some(prev), None) => secants[prev],
+                (None, None) => 0.0,
+            })
+            .collect::<Vec<_>>();
+        //Scale down slopes that are steep enough to overshoot
+        for (i, &secant) in secants.iter().enumerate() {
+            if secant == 0.0 {
+                slopes[i] = 0.0;
+                slopes[i + 1] = 0.0;
+                continue;
+            }
+            let a = slopes[i] / secant;
+            let b = slopes[i + 1] / secant;
+            if a.powi(2) + b.powi(2) > 9.0 {
+                let tau = 3.0 / (a.powi(2) + b.powi(2)).sqrt();
+                slopes[i] = tau * a * secant;
+                slopes[i + 1] = tau * b * secant;
+            }
+        }
+        MonotoneCubicCurve(HermiteCurve::from_slopes(&data, slopes))
+    }
+}
+
+/// The smoothest curve through every key, with continuous velocity and acceleration, coming to rest in acceleration at the ends.
+/// A change to one key affects the whole curve, and it can overshoot.
+#[derive(Clone)]
+pub struct NaturalCubicCurve<T: Float + Clone + Send + Sync + Display>(pub HermiteCurve<T>);
+
+impl<T: Float + Clone + Send + Sync + Display> Curve for NaturalCubicCurve<T> {
+    type Value = T;
+    fn get_value(&self, t: Self::Value) -> Self::Value {
+        self.0.get_value(t)
+    }
+}
+
+impl<T: Float + Clone + Send + Sync + Display> ChainableCurve for NaturalCubicCurve<T> {
+    type Value = T;
+    fn new(
+        data: Vec<(
+            <Self as ChainableCurve>::Value,
+            <Self as ChainableCurve>::Value,
+        )>,
+    ) -> Self {
+        let mut data = data;
+        sort_keys(&mut data, |p| p.0);
+        merge_duplicate_keys(&mut data, |p| p.0);
+        let points = points_to_f64(&data);
+        let n = points.len();
+        if n < 3 {
+            let slopes = secant_slopes(&points);
+            let slope = slopes.first().copied().unwrap_or(0.0);
+            return NaturalCubicCurve(HermiteCurve::from_slopes(&data, vec![slope; n]));
+        }
+        let secants = secant_slopes(&points);
+        let widths = points
+            .windows(2)
+            .map(|w| w[1].0 - w[0].0)
+            .collect::<Vec<_>>();
+
+        //Solve the tridiagonal system for the second derivative at each interior key (Thomas algorithm), the ends being 0
+        let mut upper = vec![0.0; n];
+        let mut rhs = vec![0.0; n];
+        for i in 1..n - 1 {
+            let lower = widths[i - 1];
+            let diagonal = 2.0 * (widths[i - 1] + widths[i]) - lower * upper[i - 1];
+            upper[i] = widths[i] / diagonal;
+            rhs[i] = (6.0 * (secants[i] - secants[i - 1]) - lower * rhs[i - 1]) / diagonal;
+        }
+        let mut second = vec![0.0; n];
+        for i in (1..n - 1).rev() {
+            second[i] = rhs[i] - upper[i] * second[i + 1];
+        }
+
+        let slopes = (0..n)
+            .map(|i| match secants.get(i) {
+                Some(secant) => secant - widths[i] * (2.0 * second[i] + second[i + 1]) / 6.0,
+                None => secants[i - 1] + widths[i - 1] * (second[i - 1] + 2.0 * second[i]) / 6.0,
+            })
+            .collect();
+        NaturalCubicCurve(HermiteCurve::from_slopes(&data, slopes))
+    }
+}
@@ -0,0 +1,118 @@
+use super::{cubic_bezier, solve_cubic_bezier};
+use std::f64::consts::PI;
+
+/// The standard easing functions (see easings.net), plus CSS style `cubic-bezier(x1, y1, x2, y2)`.
+/// Each maps progress through a segment (0.0 to 1.0) to how far the value has moved, which can overshoot for Back and Elastic.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    InQuad,
+    OutQuad,
+    InOutQuad,
+    InCubic,
+    OutCubic,
+    InOutCubic,
+    InQuart,
+    OutQuart,
+    InOutQuart,
+    InExpo,
+    OutExpo,
+    InOutExpo,
+    InBack,
+    OutBack,
+    InOutBack,
+    InElastic,
+    OutElastic,
+    InOutElastic,
+    InBounce,
+    OutBounce,
+    InOutBounce,
+    /// Same as CSS, x1 and x2 are clamped to 0.0..=1.0
+    CubicBezier(f64, f64, f64, f64),
+}
+
+impl Easing {
+    /// CSS `ease`
+    pub const EASE: Easing = Easing::CubicBezier(0.25, 0.1, 0.25, 1.0);
+    /// CSS `ease-in`
+    pub const EASE_IN: Easing = Easing::CubicBezier(0.42, 0.0, 1.0, 1.0);
+    /// CSS `ease-out`
+    pub const EASE_OUT: Easing = Easing::CubicBezier(0.0, 0.0, 0.58, 1.0);
+    /// CSS `ease-in-out`
+    pub const EASE_IN_OUT: Easing = Easing::CubicBezier(0.42, 0.0, 0.58, 1.0);
+
+    pub fn ease(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        const BACK: f64 = 1.70158;
+        const BACK_IN_OUT: f64 = BACK * 1.525;
+        const ELASTIC: f64 = 2.0 * PI / 3.0;
+        const ELASTIC_IN_OUT: f64 = 2.0 * PI / 4.5;
+        match *self {
+            Easing::Linear => t,
+            Easing::InQuad => t.powi(2),
+            Easing::OutQuad => 1.0 - (1.0 - t).powi(2),
+            Easing::InOutQuad => in_out(t, |t| t.powi(2)),
+            Easing::InCubic => t.powi(3),
+            Easing::OutCubic => 1.0 - (1.0 - t).powi(3),
+            Easing::InOutCubic => in_out(t, |t| t.powi(3)),
+            Easing::InQuart => t.powi(4),
+            Easing::OutQuart => 1.0 - (1.0 - t).powi(4),
+            Easing::InOutQuart => in_out(t, |t| t.powi(4)),
+            Easing::InExpo => expo_in(t),
+            Easing::OutExpo => 1.0 - expo_in(1.0 - t),
+            Easing::InOutExpo => in_out(t, expo_in),
+            Easing::InBack => (BACK + 1.0) * t.powi(3) - BACK * t.powi(2),
+            Easing::OutBack => 1.0 + (BACK + 1.0) * (t - 1.0).powi(3) + BACK * (t - 1.0).powi(2),
+            Easing::InOutBack => in_out(t, |t| {
+                (BACK_IN_OUT + 1.0) * t.powi(3) - BACK_IN_OUT * t.powi(2)
+            }),
+            Easing::InElastic => elastic_in(t, ELASTIC, 10.75),
+            Easing::OutElastic => 1.0 - elastic_in(1.0 - t, ELASTIC, 10.75),
+            Easing::InOutElastic => in_out(t, |t| elastic_in(t, ELASTIC_IN_OUT, 11.125)),
+            Easing::InBounce => 1.0 - bounce_out(1.0 - t),
+            Easing::OutBounce => bounce_out(t),
+            Easing::InOutBounce => in_out(t, |t| 1.0 - bounce_out(1.0 - t)),
+            Easing::CubicBezier(x1, y1, x2, y2) => {
+                cubic_bezier(0.0, y1, y2, 1.0, solve_cubic_bezier(x1, x2, t))
+            }
+        }
+    }
+}
+
+fn in_out<F: Fn(f64) -> f64>(t: f64, ease_in: F) -> f64 {
+    //! Eases in over the first half, then mirrors it to ease out over the second
+    if t < 0.5 {
+        ease_in(t * 2.0) / 2.0
+    } else {
+        1.0 - ease_in((1.0 - t) * 2.0) / 2.0
+    }
+}
+
+fn expo_in(t: f64) -> f64 {
+    if t <= 0.0 {
+        return 0.0;
+    }
+    2f64.powf(10.0 * t - 10.0)
+}
+
+fn elastic_in(t: f64, period: f64, phase: f64) -> f64 {
+    if t <= 0.0 || t >= 1.0 {
+        return t;
+    }
+    -(2f64.powf(10.0 * t - 10.0)) * ((t * 10.0 - phase) * period).sin()
+}
+
+fn bounce_out(t: f64) -> f64 {
+    const N: f64 = 7.5625;
+    const D: f64 = 2.75;
+    if t < 1.0 / D {
+        N * t.powi(2)
+    } else if t < 2.0 / D {
+        N * (t - 1.5 / D).powi(2) + 0.75
+    } else if t < 2.5 / D {
+        N * (t - 2.25 / D).powi(2) + 0.9375
+    } else {
+        N * (t - 2.625 / D).powi(2) + 0.984375
+    }
+}
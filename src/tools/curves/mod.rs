@@ -2,6 +2,7 @@ use dyn_clone::{clone_trait_object, DynClone};
 use num_traits::Float;
 
 pub mod chainable_curves;
+pub mod easing;
 pub mod single_curves;
 
 pub trait Curve: DynClone + Send + Sync {
@@ -24,3 +25,60 @@ pub(crate) fn max_f<T: Float + PartialOrd>(a: T, b: T) -> T {
     }
     b
 }
+
+pub(crate) fn sort_keys<K, T: Float, F: Fn(&K) -> T>(keys: &mut Vec<K>, time: F) {
+    //! Drops keys at non-finite times and sorts the rest by time, so segments can be found by binary search.
+    //! Keys sharing a time stay in the order given, making an instant jump from the first to the last.
+    keys.retain(|k| time(k).is_finite());
+    keys.sort_by(|a, b| time(a).partial_cmp(&time(b)).unwrap());
+}
+
+pub(crate) fn merge_duplicate_keys<K, T: Float, F: Fn(&K) -> T>(keys: &mut Vec<K>, time: F) {
+    //! Keeps only the last of any keys sorted by sort_keys that share a time, for curves that divide by the width of a segment when built
+    keys.reverse();
+    keys.dedup_by(|a, b| time(a) == time(b));
+    keys.reverse();
+}
+
+pub(crate) fn segment_end<K, T: Float, F: Fn(&K) -> T>(keys: &[K], time: F, t: T) -> usize {
+    //! The index of the first key after t, in keys sorted by sort_keys. Never lands on a zero length segment, since it skips past every key at t.
+    keys.partition_point(|k| time(k) <= t)
+}
+
+pub(crate) fn cubic_bezier(p0: f64, p1: f64, p2: f64, p3: f64, s: f64) -> f64 {
+    //! One axis of a cubic bezier at parameter s
+    let inv = 1.0 - s;
+    inv.powi(3) * p0 + 3.0 * inv.powi(2) * s * p1 + 3.0 * inv * s.powi(2) * p2 + s.powi(3) * p3
+}
+
+pub(crate) fn solve_cubic_bezier(x1: f64, x2: f64, x: f64) -> f64 {
+    //! Finds the parameter where a bezier going from x = 0 to x = 1 (with control points x1 and x2 between them) reaches x
+    let (x1, x2, x) = (x1.clamp(0.0, 1.0), x2.clamp(0.0, 1.0), x.clamp(0.0, 1.0));
+    //Newton's method converges quickly for most curves...
+    let mut s = x;
+    for _ in 0..8 {
+        let error = cubic_bezier(0.0, x1, x2, 1.0, s) - x;
+        if error.abs() < 1e-9 {
+            return s;
+        }
+        let inv = 1.0 - s;
+        let slope =
+            3.0 * inv.powi(2) * x1 + 6.0 * inv * s * (x2 - x1) + 3.0 * s.powi(2) * (1.0 - x2);
+        if slope.abs() < 1e-9 {
+            break;
+        }
+        s = (s - error / slope).clamp(0.0, 1.0);
+    }
+    //...but falls back to bisection where the curve flattens out
+    let (mut low, mut high) = (0.0, 1.0);
+    s = x;
+    for _ in 0..64 {
+        if cubic_bezier(0.0, x1, x2, 1.0, s) < x {
+            low = s;
+        } else {
+            high = s;
+        }
+        s = (low + high) / 2.0;
+    }
+    s
+}
@@ -1,15 +1,20 @@
 use crate::prelude::{
     chainable_curves::ChainableCurve,
-    chainable_curves::{LinearPiecewiseCurve, SmoothCurve},
-    Curve, Point,
+    chainable_curves::{
+        CatmullRomCurve, CubicBezierCurve, EasingCurve, LinearPiecewiseCurve, MonotoneCubicCurve,
+        NaturalCubicCurve, SmoothCurve,
+    },
+    Curve, Easing, Point, Rgba,
 };
+use crate::tools::curves::{cubic_bezier, segment_end, solve_cubic_bezier, sort_keys};
 use dyn_clone::{clone_trait_object, DynClone};
 use num_traits::Float;
+use std::sync::Arc;
 use std::{fmt::Display, time::Duration};
 
 /// Trait for defining keyframes structs. Is defined for chainable and non-chainable curves, but is only really useful for non-chainable and only serves as a bloated abstraction when used with chainable.
 pub trait KeyFrames: DynClone {
-    type Value: Clone + Display + Send + Sync;
+    type Value: Clone + Send + Sync;
     fn get_value(&self, time: Duration) -> Self::Value;
 }
 
@@ -17,6 +22,7 @@ clone_trait_object!(KeyFrames<Value = f32>);
 clone_trait_object!(KeyFrames<Value = f64>);
 clone_trait_object!(KeyFrames<Value = Point<f32>>);
 clone_trait_object!(KeyFrames<Value = Point<f64>>);
+clone_trait_object!(KeyFrames<Value = Rgba<u8>>);
 
 #[derive(Clone)]
 pub struct ScalarKeyFrames<
@@ -58,3 +64,453 @@ impl<
 
 pub type SmoothKeyframes<T> = ScalarKeyFrames<T, SmoothCurve<T>>;
 pub type LinearKeyframes<T> = ScalarKeyFrames<T, LinearPiecewiseCurve<T>>;
+pub type BezierKeyframes<T> = ScalarKeyFrames<T, CubicBezierCurve<T>>;
+pub type EasedKeyframes<T> = ScalarKeyFrames<T, EasingCurve<T>>;
+pub type CatmullRomKeyframes<T> = ScalarKeyFrames<T, CatmullRomCurve<T>>;
+pub type MonotoneKeyframes<T> = ScalarKeyFrames<T, MonotoneCubicCurve<T>>;
+pub type NaturalSplineKeyframes<T> = ScalarKeyFrames<T, NaturalCubicCurve<T>>;
+
+impl<T: Clone + Display + Float + Send + Sync + From<f64>> EasedKeyframes<T> {
+    pub fn with_easing(data: Vec<(f64, T)>, easing: Easing) -> Self {
+        //! Ex: `EasedKeyframes::with_easing(vec![(0.0, 0.0), (1.0, 1.0)], Easing::OutBounce)`
+        Self {
+            curve: EasingCurve::with_easing(
+                data.iter()
+                    .map(|(t, v)| (From::<f64>::from(*t), *v))
+                    .collect::<Vec<_>>(),
+                easing,
+            ),
+        }
+    }
+}
+
+/// How a keyframe gets to the next one, the last keyframe's interpolation is never used
+#[derive(Clone)]
+pub enum Interpolation {
+    /// Stays at this keyframe's value until the next keyframe (step)
+    Hold,
+    Linear,
+    /// Accelerates to the halfway point then slows down, same as SmoothCurve
+    Smooth,
+    Eased(Easing),
+    /// Handles as fractions of the segment's (duration, change in value), out_handle offset from this keyframe and in_handle offset from the next
+    Bezier {
+        out_handle: (f64, f64),
+        in_handle: (f64, f64),
+    },
+    /// Maps progress through the segment (0.0 to 1.0) to how far the value has moved
+    Custom(Arc<dyn Fn(f64) -> f64 + Send + Sync>),
+}
+
+impl Interpolation {
+    pub fn custom<F: Fn(f64) -> f64 + Send + Sync + 'static>(func: F) -> Self {
+        Interpolation::Custom(Arc::new(func))
+    }
+    pub fn progress(&self, t: f64) -> f64 {
+        //! How far the value has moved at progress t through the segment
+        match self {
+            Interpolation::Hold => 0.0,
+            Interpolation::Linear => t,
+            Interpolation::Smooth => Easing::InOutQuad.ease(t),
+            Interpolation::Eased(easing) => easing.ease(t),
+            Interpolation::Bezier {
+                out_handle,
+                in_handle,
+            } => cubic_bezier(
+                0.0,
+                out_handle.1,
+                1.0 + in_handle.1,
+                1.0,
+                solve_cubic_bezier(out_handle.0, 1.0 + in_handle.0, t),
+            ),
+            Interpolation::Custom(func) => func(t),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Keyframe<T: Clone + Display + Float + Send + Sync> {
+    /// In seconds
+    pub time: f64,
+    pub value: T,
+    pub interpolation: Interpolation,
+}
+
+impl<T: Clone + Display + Float + Send + Sync> Keyframe<T> {
+    pub fn new(time: f64, value: T, interpolation: Interpolation) -> Self {
+        Self {
+            time,
+            value,
+            interpolation,
+        }
+    }
+}
+
+/// Keyframes that each carry their own interpolation to the next keyframe, like a timeline editor.
+/// Ex:
+/// ```
+/// use shanimation_rs::prelude::*;
+///
+/// InterpolatedKeyFrames::new(vec![
+///     Keyframe::new(0.0, 0.0, Interpolation::Eased(Easing::InCubic)),
+///     Keyframe::new(1.0, 0.5, Interpolation::Hold),
+///     Keyframe::new(2.0, 1.0, Interpolation::Linear),
+/// ]);
+///```
+#[derive(Clone)]
+pub struct InterpolatedKeyFrames<T: Clone + Display + Float + Send + Sync> {
+    pub keyframes: Vec<Keyframe<T>>,
+}
+
+impl<T: Clone + Display + Float + Send + Sync> InterpolatedKeyFrames<T> {
+    pub fn new(keyframes: Vec<Keyframe<T>>) -> Self {
+        //! Sorts the keyframes like ChainableCurve::new, if you change keyframes afterwards it has to stay sorted this way
+        let mut keyframes = keyframes;
+        sort_keys(&mut keyframes, |k| k.time);
+        Self { keyframes }
+    }
+}
+
+impl<T: Clone + Display + Float + Send + Sync> KeyFrames for InterpolatedKeyFrames<T> {
+    type Value = T;
+    fn get_value(&self, time: Duration) -> Self::Value {
+        let t = time.as_secs_f64();
+        if self.keyframes.is_empty() {
+            return T::nan();
+        }
+        if self.keyframes.len() == 1 || t <= self.keyframes[0].time {
+            return self.keyframes[0].value;
+        }
+        if t >= self.keyframes.last().unwrap().time {
+            return self.keyframes.last().unwrap().value;
+        }
+
+        let end = segment_end(&self.keyframes, |k| k.time, t);
+        let (k1, k2) = (&self.keyframes[end - 1], &self.keyframes[end]);
+        let progress = k1
+            .interpolation
+            .progress((t - k1.time) / (k2.time - k1.time));
+        k1.value + (k2.value - k1.value) * T::from(progress).unwrap()
+    }
+}
+
+/// Animates a point, each axis following its own curve C, so a CatmullRomCurve moves it along a smooth path through the keys.
+/// Ex:
+/// ```
+/// use shanimation_rs::prelude::*;
+///
+/// let path = SplinePointKeyframes::new(vec![
+///     (0.0, Point::new(0.1, 0.5)),
+///     (1.0, Point::new(0.5, 0.2)),
+///     (2.0, Point::new(0.9, 0.5)),
+/// ]);
+/// let rotation = path.auto_orient(); //keeps a renderable facing along the path
+///```
+#[derive(Clone)]
+pub struct PointKeyFrames<T: Clone + Display + Float + Send + Sync, C: Curve<Value = T>> {
+    pub x: C,
+    pub y: C,
+    keys: Vec<(f64, Point<T>)>,
+}
+
+impl<
+        T: Clone + Display + Float + Send + Sync,
+        C: ChainableCurve<Value = T> + Curve<Value = T> + Clone,
+    > PointKeyFrames<T, C>
+{
+    pub fn new(data: Vec<(f64, Point<T>)>) -> Self {
+        //! Times in seconds
+        let mut data = data;
+        sort_keys(&mut data, |k| k.0);
+        let axis = |get: fn(&Point<T>) -> T| {
+            C::new(
+                data.iter()
+                    .map(|(t, p)| (T::from(*t).unwrap(), get(p)))
+                    .collect::<Vec<_>>(),
+            )
+        };
+        Self {
+            x: axis(|p| p.x),
+            y: axis(|p| p.y),
+            keys: data,
+        }
+    }
+    pub fn rotation_at(&self, time: Duration) -> f64 {
+        //! The direction of travel, counter-clockwise in radians like RenderableParams::rotation, 0.0 being to the right.
+        //! Where the point isn't moving (before the first key, after the last, or while holding) it keeps the direction of the closest movement.
+        const STEP: f64 = 1e-4;
+        let t = time.as_secs_f64();
+        let position = |t: f64| {
+            let t = T::from(t).unwrap();
+            Point::new(
+                self.x.get_value(t).to_f64().unwrap(),
+                self.y.get_value(t).to_f64().unwrap(),
+            )
+        };
+        let velocity = position(t + STEP) - position(t - STEP);
+        if velocity.x != 0.0 || velocity.y != 0.0 {
+            //Screen y points down, so flip it to get counter-clockwise
+            return (-velocity.y).atan2(velocity.x);
+        }
+
+        let current = segment_end(&self.keys, |k| k.0, t).saturating_sub(1);
+        self.keys
+            .windows(2)
+            .map(|w| {
+                w[1].1.map_both(|v| v.to_f64().unwrap()) - w[0].1.map_both(|v| v.to_f64().unwrap())
+            })
+            .enumerate()
+            .filter(|(_, chord)| chord.x != 0.0 || chord.y != 0.0)
+            .min_by_key(|(i, _)| i.abs_diff(current))
+            .map(|(_, chord)| (-chord.y).atan2(chord.x))
+            .unwrap_or(0.0)
+    }
+    pub fn auto_orient(&self) -> AutoOrient<T, C> {
+        AutoOrient(self.clone())
+    }
+}
+
+impl<
+        T: Clone + Display + Float + Send + Sync,
+        C: ChainableCurve<Value = T> + Curve<Value = T> + Clone,
+    > KeyFrames for PointKeyFrames<T, C>
+{
+    type Value = Point<T>;
+    fn get_value(&self, time: Duration) -> Self::Value {
+        let t = T::from(time.as_secs_f64()).unwrap();
+        Point::new(self.x.get_value(t), self.y.get_value(t))
+    }
+}
+
+/// The rotation of a renderable following a PointKeyFrames path, see PointKeyFrames::rotation_at
+#[derive(Clone)]
+pub struct AutoOrient<T: Clone + Display + Float + Send + Sync, C: Curve<Value = T>>(
+    pub PointKeyFrames<T, C>,
+);
+
+impl<
+        T: Clone + Display + Float + Send + Sync,
+        C: ChainableCurve<Value = T> + Curve<Value = T> + Clone,
+    > KeyFrames for AutoOrient<T, C>
+{
+    type Value = f64;
+    fn get_value(&self, time: Duration) -> Self::Value {
+        self.0.rotation_at(time)
+    }
+}
+
+pub type LinearPointKeyframes<T> = PointKeyFrames<T, LinearPiecewiseCurve<T>>;
+pub type SplinePointKeyframes<T> = PointKeyFrames<T, CatmullRomCurve<T>>;
+
+/// The colour space ColorKeyFrames interpolates in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorSpace {
+    /// Mixes light like it physically does, without the dark band you get halfway between colours in plain sRGB
+    #[default]
+    LinearRgb,
+    /// Perceptually uniform, so lightness and hue change evenly
+    Oklab,
+    /// Goes around the colour wheel (the short way), keeping colours saturated
+    Hsv,
+}
+
+impl ColorSpace {
+    fn encode(self, color: Rgba<u8>) -> [f64; 3] {
+        let [r, g, b, _] = color.0;
+        match self {
+            ColorSpace::LinearRgb => [r, g, b].map(srgb_to_linear),
+            ColorSpace::Oklab => linear_to_oklab([r, g, b].map(srgb_to_linear)),
+            ColorSpace::Hsv => rgb_to_hsv([r, g, b].map(|v| v as f64 / 255.0)),
+        }
+    }
+    fn decode(self, components: [f64; 3]) -> [u8; 3] {
+        match self {
+            ColorSpace::LinearRgb => components.map(linear_to_srgb),
+            ColorSpace::Oklab => oklab_to_linear(components).map(linear_to_srgb),
+            ColorSpace::Hsv => {
+                hsv_to_rgb(components).map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+            }
+        }
+    }
+}
+
+/// Animates a colour, each channel following its own curve C in the chosen colour space. Alpha is interpolated as is.
+/// Ex:
+/// ```
+/// use shanimation_rs::prelude::*;
+///
+/// LinearColorKeyframes::new(
+///     vec![(0.0, Rgba([255, 0, 0, 255])), (1.0, Rgba([0, 0, 255, 255]))],
+///     ColorSpace::Oklab,
+/// );
+///```
+#[derive(Clone)]
+pub struct ColorKeyFrames<C: Curve<Value = f64>> {
+    space: ColorSpace,
+    channels: [C; 4],
+}
+
+impl<C: ChainableCurve<Value = f64> + Curve<Value = f64> + Clone> ColorKeyFrames<C> {
+    pub fn new(data: Vec<(f64, Rgba<u8>)>, space: ColorSpace) -> Self {
+        //! Times in seconds
+        let mut data = data;
+        sort_keys(&mut data, |k| k.0);
+        let mut components = data
+            .iter()
+            .map(|(_, color)| {
+                let [a, b, c] = space.encode(*color);
+                [a, b, c, color.0[3] as f64 / 255.0]
+            })
+            .collect::<Vec<_>>();
+        if space == ColorSpace::Hsv {
+            unwrap_hues(&mut components);
+        }
+        let channels = [0, 1, 2, 3].map(|i| {
+            C::new(
+                data.iter()
+                    .zip(&components)
+                    .map(|((t, _), c)| (*t, c[i]))
+                    .collect::<Vec<_>>(),
+            )
+        });
+        Self { space, channels }
+    }
+    pub fn space(&self) -> ColorSpace {
+        self.space
+    }
+}
+
+impl<C: ChainableCurve<Value = f64> + Curve<Value = f64> + Clone> KeyFrames for ColorKeyFrames<C> {
+    type Value = Rgba<u8>;
+    fn get_value(&self, time: Duration) -> Self::Value {
+        let t = time.as_secs_f64();
+        let [a, b, c, alpha] = [0, 1, 2, 3].map(|i| self.channels[i].get_value(t));
+        let [r, g, b] = self.space.decode([a, b, c]);
+        Rgba([r, g, b, (alpha.clamp(0.0, 1.0) * 255.0).round() as u8])
+    }
+}
+
+pub type LinearColorKeyframes = ColorKeyFrames<LinearPiecewiseCurve<f64>>;
+pub type SmoothColorKeyframes = ColorKeyFrames<SmoothCurve<f64>>;
+
+fn unwrap_hues(components: &mut [[f64; 4]]) {
+    //! Moves each hue by whole turns to within half a turn of the one before, so interpolating goes the short way round.
+    //! Greys have no hue, so they take the one before them (or after, at the start) instead of swinging through the wheel.
+    let mut previous = components.iter().find(|c| c[1] > 0.0).map_or(0.0, |c| c[0]);
+    for c in components.iter_mut() {
+        if c[1] > 0.0 {
+            c[0] += ((previous - c[0]) / 360.0).round() * 360.0;
+        } else {
+            c[0] = previous;
+        }
+        previous = c[0];
+    }
+}
+
+fn srgb_to_linear(v: u8) -> f64 {
+    let v = v as f64 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(v: f64) -> u8 {
+    let v = v.clamp(0.0, 1.0);
+    let v = if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (v * 255.0).round() as u8
+}
+
+fn linear_to_oklab([r, g, b]: [f64; 3]) -> [f64; 3] {
+    //! See https://bottosson.github.io/posts/oklab/
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+
+fn oklab_to_linear([lightness, a, b]: [f64; 3]) -> [f64; 3] {
+    let l = (lightness + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m = (lightness - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s = (lightness - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+    [
+        4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+        -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+    ]
+}
+
+fn rgb_to_hsv([r, g, b]: [f64; 3]) -> [f64; 3] {
+    //! Hue in degrees, saturation and value 0.0 to 1.0
+    let max = r.max(g).max(b);
+    let delta = max - r.min(g).min(b);
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+    [hue, saturation, max]
+}
+
+fn hsv_to_rgb([hue, saturation, value]: [f64; 3]) -> [f64; 3] {
+    let (saturation, value) = (saturation.clamp(0.0, 1.0), value.clamp(0.0, 1.0));
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let [r, g, b] = match sector as usize {
+        0 => [chroma, x, 0.0],
+        1 => [x, chroma, 0.0],
+        2 => [0.0, chroma, x],
+        3 => [0.0, x, chroma],
+        4 => [x, 0.0, chroma],
+        _ => [chroma, 0.0, x],
+    };
+    [r, g, b].map(|v| v + value - chroma)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_sharing_a_time_jump() {
+        let keyframes = LinearKeyframes::new(vec![(0.0, 0.0), (1.0, 0.0), (1.0, 5.0), (2.0, 5.0)]);
+        let at = |secs: f64| keyframes.get_value(Duration::from_secs_f64(secs));
+        assert_eq!(at(0.5), 0.0);
+        assert_eq!(at(0.99), 0.0);
+        assert_eq!(at(1.0), 5.0);
+        assert_eq!(at(1.5), 5.0);
+
+        let unsorted = LinearKeyframes::new(vec![(2.0, 5.0), (1.0, 0.0), (1.0, 5.0), (0.0, 0.0)]);
+        assert_eq!(unsorted.get_value(Duration::from_secs_f64(0.99)), 0.0);
+        assert_eq!(unsorted.get_value(Duration::from_secs_f64(1.5)), 5.0);
+    }
+
+    #[test]
+    fn nan_time_gives_nan() {
+        let data = vec![(0.0, 0.0), (1.0, 1.0)];
+        assert!(LinearPiecewiseCurve::new(data.clone())
+            .get_value(f64::NAN)
+            .is_nan());
+        assert!(SmoothCurve::new(data.clone()).get_value(f64::NAN).is_nan());
+        assert!(CubicBezierCurve::new(data.clone())
+            .get_value(f64::NAN)
+            .is_nan());
+        assert!(EasingCurve::new(data.clone()).get_value(f64::NAN).is_nan());
+        assert!(CatmullRomCurve::new(data).get_value(f64::NAN).is_nan());
+    }
+}
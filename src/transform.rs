@@ -0,0 +1,103 @@
+use crate::prelude::*;
+use std::ops::Mul;
+
+/// A 2D affine transform in pixel space (y pointing down), stored as a 3x3 matrix acting on column vectors.
+/// `a * b` applies `b` first, then `a`, so a renderable's world transform is `parent_world * local`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub matrix: [[f64; 3]; 3],
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+    };
+    pub fn translation(offset: Point<f64>) -> Self {
+        Self {
+            matrix: [[1.0, 0.0, offset.x], [0.0, 1.0, offset.y], [0.0, 0.0, 1.0]],
+        }
+    }
+    pub fn rotation(angle: f64) -> Self {
+        //! Rotates counter-clockwise on screen by `angle` radians.
+        let (sin, cos) = angle.sin_cos();
+        Self {
+            matrix: [[cos, sin, 0.0], [-sin, cos, 0.0], [0.0, 0.0, 1.0]],
+        }
+    }
+    pub fn scale(scale: Point<f64>) -> Self {
+        Self {
+            matrix: [[scale.x, 0.0, 0.0], [0.0, scale.y, 0.0], [0.0, 0.0, 1.0]],
+        }
+    }
+    pub fn skew(skew: Point<f64>) -> Self {
+        //! Shears by the given angles in radians: x slants vertical edges, y slants horizontal edges.
+        Self {
+            matrix: [
+                [1.0, skew.x.tan(), 0.0],
+                [skew.y.tan(), 1.0, 0.0],
+                [0.0, 0.0, 1.0],
+            ],
+        }
+    }
+    pub fn apply(&self, point: Point<f64>) -> Point<f64> {
+        let m = &self.matrix;
+        Point::new(
+            m[0][0] * point.x + m[0][1] * point.y + m[0][2],
+            m[1][0] * point.x + m[1][1] * point.y + m[1][2],
+        )
+    }
+    pub fn origin(&self) -> Point<f64> {
+        //! Where the local (0, 0) ends up.
+        Point::new(self.matrix[0][2], self.matrix[1][2])
+    }
+    pub fn x_axis(&self) -> Point<f64> {
+        //! Where a local step of (1, 0) goes, ignoring translation.
+        Point::new(self.matrix[0][0], self.matrix[1][0])
+    }
+    pub fn y_axis(&self) -> Point<f64> {
+        //! Where a local step of (0, 1) goes, ignoring translation.
+        Point::new(self.matrix[0][1], self.matrix[1][1])
+    }
+    pub fn determinant(&self) -> f64 {
+        let m = &self.matrix;
+        m[0][0] * m[1][1] - m[0][1] * m[1][0]
+    }
+    pub fn inverse(&self) -> Option<Self> {
+        //! None when the transform collapses everything onto a line or point (eg. a scale of 0).
+        let det = self.determinant();
+        if det.abs() < f64::EPSILON {
+            return None;
+        }
+        let m = &self.matrix;
+        let (a, b, c, d) = (m[1][1] / det, -m[0][1] / det, -m[1][0] / det, m[0][0] / det);
+        Some(Self {
+            matrix: [
+                [a, b, -(a * m[0][2] + b * m[1][2])],
+                [c, d, -(c * m[0][2] + d * m[1][2])],
+                [0.0, 0.0, 1.0],
+            ],
+        })
+    }
+}
+
+impl Mul for Transform {
+    type Output = Transform;
+
+    fn mul(self, rhs: Transform) -> Self::Output {
+        let mut matrix = [[0.0; 3]; 3];
+        for (row, out) in matrix.iter_mut().enumerate() {
+            for (col, v) in out.iter_mut().enumerate() {
+                *v = (0..3)
+                    .map(|i| self.matrix[row][i] * rhs.matrix[i][col])
+                    .sum();
+            }
+        }
+        Transform { matrix }
+    }
+}